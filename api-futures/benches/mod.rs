@@ -38,7 +38,7 @@ service!("/a/b/c/d" => D);
 
 #[bench]
 fn clone_router_0(b: &mut Bencher) {
-    let router = RouterBuilder::new().build();
+    let router = RouterBuilder::new().build().unwrap();
 
     b.iter(|| {
         test::black_box(router.clone())
@@ -54,7 +54,8 @@ fn clone_router_6(b: &mut Bencher) {
         .post(Root)
         .post(A)
         .post(B)
-        .build();
+        .build()
+        .unwrap();
 
     b.iter(|| {
         test::black_box(router.clone())
@@ -74,7 +75,8 @@ fn clone_router_10(b: &mut Bencher) {
         .post(B)
         .post(C)
         .post(D)
-        .build();
+        .build()
+        .unwrap();
 
     b.iter(|| {
         test::black_box(router.clone())
@@ -13,12 +13,25 @@
 //! So when we encounter an application error we unwrap it to a `Result::Ok`
 //! variant, but with an error status code, like `StatusCode::NotFound`.
 //! That way the client is properly notified that something went wrong.
+//!
+//! The body of an error response is an RFC 7807 problem details document,
+//! with a `Content-Type` of `application/problem+json`.
+//! The `ProblemDetails` trait decides the status code and contents of the
+//! document for each `ErrorKind`.
+//! Errors the client can do something about, like invalid input, include
+//! their message as the `detail`, but server errors don't, so internal
+//! details aren't leaked.
+//! New error kinds can add their own members to the document by matching
+//! them in `ProblemDetails::extensions`.
 
+use std::time::Duration;
 use tokio_timer;
+use hyper::{self, Method};
 
 error_chain! {
     foreign_links {
         tokio_timer::TimerError, Timer;
+        hyper::Error, Hyper;
     }
 
     errors {
@@ -30,15 +43,239 @@ error_chain! {
             description("the route was not specified")
             display("the route was not specified")
         }
-        MethodNotSupported {
+        InvalidParam(name: String, reason: String) {
+            description("a url parameter is invalid")
+            display("the url parameter '{}' is invalid: {}", name, reason)
+        }
+        BodyTooLarge(limit: usize) {
+            description("the request body is too large")
+            display("the request body is larger than the limit of {} bytes", limit)
+        }
+        UnsupportedMediaType(content_type: Option<String>) {
+            description("the request body has an unsupported content type")
+            display("the request body has an unsupported content type: {:?}", content_type)
+        }
+        InvalidJson(msg: String) {
+            description("the request body is not valid json")
+            display("the request body is not valid json: {}", msg)
+        }
+        InvalidQuery(msg: String) {
+            description("the query string is invalid")
+            display("the query string is invalid: {}", msg)
+        }
+        RouteConflict(conflicts: Vec<String>) {
+            description("the router has conflicting routes")
+            display("the router has conflicting routes: {}", conflicts.join(", "))
+        }
+        UnknownRouteName(name: String) {
+            description("there is no route with the given name")
+            display("there is no route named '{}'", name)
+        }
+        MissingUrlParam(name: String, param: String) {
+            description("a parameter for the url is missing")
+            display("the route named '{}' needs a value for '{}'", name, param)
+        }
+        HandlerTimedOut(timeout: Duration) {
+            description("the handler took too long to respond")
+            display("the handler didn't respond within {}ms", millis(*timeout))
+        }
+        TimeoutTooLong(timeout: Duration, max: Duration) {
+            description("the timeout is longer than the timer supports")
+            display("the timeout of {}ms is longer than the maximum of {}ms", millis(*timeout), millis(*max))
+        }
+        NoConcurrency {
+            description("the concurrency limit doesn't allow any requests")
+            display("the concurrency limit must allow at least 1 request in flight")
+        }
+        Overloaded(retry_after: Duration) {
+            description("the server is handling too many requests")
+            display("the server is handling too many requests, try again in {}s", secs(*retry_after))
+        }
+        HandlerPanicked {
+            description("the handler panicked")
+            display("the handler panicked while handling the request")
+        }
+        StateNotFound {
+            description("the requested state was not added to the router")
+            display("the requested state was not added to the router")
+        }
+        MethodNotSupported(allowed: Vec<Method>) {
             description("the http method is not supported")
-            display("the http method is not supported")
+            display("the http method is not supported, expected one of: {:?}", allowed)
         }
     }
 }
 
+use std::collections::BTreeMap;
+use hyper::header::{Allow, ContentLength, ContentType};
+use hyper::mime::{Mime, TopLevel, SubLevel};
+use serde_json::{self, Value};
 use host::{Response, StatusCode};
 
+/// A [problem details]() document, as described by RFC 7807.
+///
+/// Problems are sent as an `application/problem+json` body.
+/// Besides the standard members, a problem can have extension members that
+/// carry more information about the error.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    /// A URI identifying the type of problem.
+    pub problem_type: String,
+    /// A short summary of the type of problem.
+    pub title: String,
+    /// The HTTP status code.
+    pub status: StatusCode,
+    /// An explanation specific to this occurrence of the problem.
+    pub detail: Option<String>,
+    /// A URI identifying this occurrence of the problem, like the request
+    /// path.
+    pub instance: Option<String>,
+    /// Extension members.
+    pub extensions: BTreeMap<String, Value>,
+}
+
+impl Problem {
+    /// Serialise the problem as json.
+    pub fn to_json(&self) -> Vec<u8> {
+        let mut doc = self.extensions.clone();
+
+        doc.insert("type".to_owned(), Value::String(self.problem_type.clone()));
+        doc.insert("title".to_owned(), Value::String(self.title.clone()));
+        doc.insert("status".to_owned(), Value::U64(self.status.to_u16() as u64));
+
+        if let Some(ref detail) = self.detail {
+            doc.insert("detail".to_owned(), Value::String(detail.clone()));
+        }
+        if let Some(ref instance) = self.instance {
+            doc.insert("instance".to_owned(), Value::String(instance.clone()));
+        }
+
+        serde_json::to_vec(&doc).expect("a problem should always serialise")
+    }
+}
+
+impl From<Problem> for Response {
+    fn from(problem: Problem) -> Response {
+        let body = problem.to_json();
+        let content_type = Mime(TopLevel::Application, SubLevel::Ext("problem+json".to_owned()), vec![]);
+
+        Response::new()
+            .status(problem.status)
+            .header(ContentType(content_type))
+            .header(ContentLength(body.len() as u64))
+            .body(body)
+    }
+}
+
+/// Describe an error as a problem details document.
+///
+/// This is the extension point for error responses.
+/// Each error kind has a status code, and can override the other members
+/// of its problem or add extension members.
+pub trait ProblemDetails {
+    /// The HTTP status code for the error.
+    fn status(&self) -> StatusCode;
+
+    /// A URI identifying the type of problem.
+    ///
+    /// The default is `about:blank`, which means the problem is described
+    /// by its status code alone.
+    fn problem_type(&self) -> String {
+        "about:blank".to_owned()
+    }
+
+    /// A short summary of the type of problem.
+    ///
+    /// The default is the reason phrase for the status code.
+    fn title(&self) -> String {
+        self.status().canonical_reason().unwrap_or("Unknown Error").to_owned()
+    }
+
+    /// An explanation specific to this occurrence of the problem.
+    fn detail(&self) -> Option<String> {
+        None
+    }
+
+    /// Add any extension members to the problem.
+    fn extensions(&self, _: &mut BTreeMap<String, Value>) {}
+
+    /// Build the problem details document.
+    fn problem(&self) -> Problem {
+        let mut extensions = BTreeMap::new();
+        self.extensions(&mut extensions);
+
+        Problem {
+            problem_type: self.problem_type(),
+            title: self.title(),
+            status: self.status(),
+            detail: self.detail(),
+            instance: None,
+            extensions: extensions,
+        }
+    }
+}
+
+impl ProblemDetails for ErrorKind {
+    fn status(&self) -> StatusCode {
+        match *self {
+            ErrorKind::NoRouteMatch(_) => StatusCode::NotFound,
+            ErrorKind::NoRouteSpecified => StatusCode::BadRequest,
+            ErrorKind::InvalidParam(..) => StatusCode::BadRequest,
+            ErrorKind::BodyTooLarge(_) => StatusCode::PayloadTooLarge,
+            ErrorKind::UnsupportedMediaType(_) => StatusCode::UnsupportedMediaType,
+            ErrorKind::InvalidJson(_) => StatusCode::BadRequest,
+            ErrorKind::InvalidQuery(_) => StatusCode::BadRequest,
+            ErrorKind::MethodNotSupported(_) => StatusCode::MethodNotAllowed,
+            ErrorKind::HandlerTimedOut(_) => StatusCode::ServiceUnavailable,
+            ErrorKind::Overloaded(_) => StatusCode::ServiceUnavailable,
+            // Catch all for any other errors, which get expressed as a 500
+            _ => StatusCode::InternalServerError,
+        }
+    }
+
+    /// The error's message, for errors the client can do something about.
+    ///
+    /// Server errors don't have a detail, so we don't leak any internal
+    /// details, except for timeouts and load shedding, which the client
+    /// might want to retry.
+    fn detail(&self) -> Option<String> {
+        match *self {
+            ErrorKind::HandlerTimedOut(_) |
+            ErrorKind::Overloaded(_) => return Some(self.to_string()),
+            _ => (),
+        }
+
+        if self.status().is_server_error() {
+            None
+        } else {
+            Some(self.to_string())
+        }
+    }
+
+    fn extensions(&self, extensions: &mut BTreeMap<String, Value>) {
+        match *self {
+            ErrorKind::InvalidParam(ref name, _) => {
+                extensions.insert("param".to_owned(), Value::String(name.clone()));
+            }
+            ErrorKind::HandlerTimedOut(timeout) => {
+                extensions.insert("timeout_ms".to_owned(), Value::U64(millis(timeout)));
+            }
+            ErrorKind::Overloaded(retry_after) => {
+                extensions.insert("retry_after_s".to_owned(), Value::U64(secs(retry_after)));
+            }
+            ErrorKind::BodyTooLarge(limit) => {
+                extensions.insert("limit".to_owned(), Value::U64(limit as u64));
+            }
+            ErrorKind::MethodNotSupported(ref allowed) => {
+                let allowed = allowed.iter().map(|method| Value::String(method.to_string())).collect();
+
+                extensions.insert("allowed".to_owned(), Value::Array(allowed));
+            }
+            _ => (),
+        }
+    }
+}
+
 impl From<Error> for Response {
     fn from(err: Error) -> Response {
         let kind = err.0;
@@ -57,13 +294,7 @@ impl<'a> From<&'a Error> for Response {
 
 impl<'a> From<&'a ErrorKind> for Response {
     fn from(err: &'a ErrorKind) -> Response {
-        match err {
-            &ErrorKind::NoRouteMatch(_) => Response::new().status(StatusCode::NotFound),
-            &ErrorKind::NoRouteSpecified => Response::new().status(StatusCode::BadRequest),
-            &ErrorKind::MethodNotSupported => Response::new().status(StatusCode::MethodNotAllowed),
-            // Catch all for any other errors, which get expressed as a 500
-            _ => Response::new().status(StatusCode::InternalServerError),
-        }
+        error_response(err, None)
     }
 }
 
@@ -72,3 +303,124 @@ impl From<ErrorKind> for Response {
         (&err).into()
     }
 }
+
+/// Build a problem response for an error that happened for a particular
+/// request path.
+pub fn error_response(err: &ErrorKind, instance: Option<&str>) -> Response {
+    let mut problem = err.problem();
+    problem.instance = instance.map(|instance| instance.to_owned());
+
+    let response: Response = problem.into();
+
+    match *err {
+        ErrorKind::MethodNotSupported(ref allowed) => response.header(Allow(allowed.clone())),
+        ErrorKind::Overloaded(retry_after) => response.header(RetryAfter(secs(retry_after))),
+        _ => response,
+    }
+}
+
+// `Retry-After` in seconds
+header! { (RetryAfter, "Retry-After") => [u64] }
+
+/// Whole seconds, rounded up so clients never retry too early.
+fn secs(duration: Duration) -> u64 {
+    if duration.subsec_nanos() > 0 {
+        duration.as_secs() + 1
+    } else {
+        duration.as_secs()
+    }
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + (duration.subsec_nanos() / 1_000_000) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+    use hyper::header::ContentType;
+    use serde_json::{self, Value};
+    use host::StatusCode;
+    use super::*;
+
+    fn to_json(problem: &Problem) -> BTreeMap<String, Value> {
+        match serde_json::from_slice(&problem.to_json()).unwrap() {
+            Value::Object(doc) => doc,
+            doc => panic!("expected an object, got {:?}", doc),
+        }
+    }
+
+    fn string(value: &str) -> Value {
+        Value::String(value.to_owned())
+    }
+
+    #[test]
+    fn client_error_has_detail() {
+        let kind = ErrorKind::InvalidParam("id".to_owned(), "not a number".to_owned());
+        let doc = to_json(&kind.problem());
+
+        assert_eq!(Some(&string("about:blank")), doc.get("type"));
+        assert_eq!(Some(&string("Bad Request")), doc.get("title"));
+        assert_eq!(Some(&Value::U64(400)), doc.get("status"));
+        assert_eq!(Some(&string(&kind.to_string())), doc.get("detail"));
+        assert_eq!(Some(&string("id")), doc.get("param"));
+    }
+
+    #[test]
+    fn server_error_has_no_detail() {
+        let doc = to_json(&ErrorKind::HandlerPanicked.problem());
+
+        assert_eq!(Some(&Value::U64(500)), doc.get("status"));
+        assert_eq!(None, doc.get("detail"));
+    }
+
+    #[test]
+    fn timeout_has_detail() {
+        let doc = to_json(&ErrorKind::HandlerTimedOut(Duration::from_millis(1500)).problem());
+
+        assert_eq!(Some(&Value::U64(503)), doc.get("status"));
+        assert!(doc.get("detail").is_some());
+        assert_eq!(Some(&Value::U64(1500)), doc.get("timeout_ms"));
+    }
+
+    #[test]
+    fn extensions_dont_overwrite_members() {
+        let mut problem = ErrorKind::NoRouteSpecified.problem();
+        problem.extensions.insert("type".to_owned(), string("urn:overwritten"));
+        problem.extensions.insert("title".to_owned(), string("Overwritten"));
+        problem.extensions.insert("status".to_owned(), Value::U64(200));
+
+        let doc = to_json(&problem);
+
+        assert_eq!(Some(&string("about:blank")), doc.get("type"));
+        assert_eq!(Some(&string("Bad Request")), doc.get("title"));
+        assert_eq!(Some(&Value::U64(400)), doc.get("status"));
+    }
+
+    #[test]
+    fn error_response_is_problem_json() {
+        let response = error_response(&ErrorKind::NoRouteMatch("/a".to_owned()), Some("/a"));
+
+        assert_eq!(StatusCode::NotFound, response.status_code());
+
+        let content_type = response.headers().get::<ContentType>().unwrap();
+        assert_eq!("application/problem+json", content_type.to_string());
+    }
+
+    #[test]
+    fn overloaded_response_has_retry_after() {
+        let response = error_response(&ErrorKind::Overloaded(Duration::from_secs(3)), None);
+
+        assert_eq!(StatusCode::ServiceUnavailable, response.status_code());
+        assert_eq!(Some(&[b"3".to_vec()][..]), response.headers().get_raw("Retry-After"));
+    }
+
+    #[test]
+    fn secs_rounds_up() {
+        assert_eq!(0, secs(Duration::from_secs(0)));
+        assert_eq!(2, secs(Duration::from_secs(2)));
+        assert_eq!(2, secs(Duration::from_millis(1500)));
+        assert_eq!(1, secs(Duration::new(0, 1)));
+    }
+}
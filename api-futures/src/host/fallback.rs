@@ -0,0 +1,114 @@
+//! # Fallback handlers
+//!
+//! When the router can't call a handler for a request it responds with
+//! the status code for the error, like `404 Not Found`, and an
+//! `application/problem+json` document describing it.
+//! Apps that want a different response, like an html page, can add
+//! fallback handlers that are given the error and the
+//! request:
+//!
+//! - `RouterBuilder::not_found` for paths that don't match a route
+//! - `RouterBuilder::method_not_allowed` for paths that match a route, but
+//! not for the request's method.
+//! The `Allow` header is added to the response if the fallback doesn't set it
+//! - `RouterBuilder::on_error` for any other error the router runs into,
+//! like invalid url parameters
+//!
+//! Errors that happen inside a handler are up to the handler itself to
+//! turn into a response.
+//!
+//! ## Examples
+//!
+//! ```
+//! # #![feature(box_syntax)]
+//! # extern crate futures;
+//! # extern crate hyper;
+//! # extern crate webapp_demo;
+//! # use futures::finished;
+//! # use hyper::header::ContentLength;
+//! # use webapp_demo::errors::Error;
+//! # use webapp_demo::host::*;
+//! # fn main() {
+//! let router = RouterBuilder::new()
+//!     .not_found(|_: Error, req: Request| -> HttpFuture {
+//!         let msg = format!("there's nothing at {}", req.path().unwrap_or(""));
+//!
+//!         box finished(Response::new()
+//!             .status(StatusCode::NotFound)
+//!             .header(ContentLength(msg.len() as u64))
+//!             .body(msg))
+//!     })
+//!     .build()
+//!     .unwrap();
+//! # }
+//! ```
+
+use std::sync::Arc;
+use futures::{finished, Future};
+use hyper::header::Allow;
+use errors::{self, Error, ErrorKind};
+use super::{HttpFuture, Request};
+
+/// A handler for requests the router couldn't handle.
+pub trait Fallback
+    where Self: Send + Sync
+{
+    /// Call the handler with the error and the request that caused it.
+    fn call(&self, err: Error, req: Request) -> HttpFuture;
+}
+
+impl<F> Fallback for F
+    where F: Fn(Error, Request) -> HttpFuture + Send + Sync
+{
+    fn call(&self, err: Error, req: Request) -> HttpFuture {
+        self(err, req)
+    }
+}
+
+/// The fallback handlers for a router.
+#[derive(Clone, Default)]
+pub struct Fallbacks {
+    pub not_found: Option<Arc<Fallback>>,
+    pub method_not_allowed: Option<Arc<Fallback>>,
+    pub on_error: Option<Arc<Fallback>>,
+}
+
+impl Fallbacks {
+    /// Respond to a request with an error.
+    ///
+    /// If there's no fallback handler for the error then the response is
+    /// a problem document for the error.
+    pub fn respond(&self, err: Error, req: Request) -> HttpFuture {
+        let fallback = match err.0 {
+            ErrorKind::NoRouteMatch(_) => self.not_found.as_ref(),
+            ErrorKind::MethodNotSupported(_) => self.method_not_allowed.as_ref(),
+            _ => self.on_error.as_ref(),
+        };
+
+        let fallback = match fallback {
+            Some(fallback) => fallback,
+            None => return box finished(errors::error_response(&err.0, req.path())),
+        };
+
+        // A 405 needs to say which methods are allowed
+        let allowed = match err.0 {
+            ErrorKind::MethodNotSupported(ref allowed) => Some(allowed.clone()),
+            _ => None,
+        };
+
+        let response = fallback.call(err, req);
+
+        match allowed {
+            Some(allowed) => {
+                box response.map(move |response| {
+                    if response.headers().has::<Allow>() {
+                        response
+                    } else {
+                        response.header(Allow(allowed))
+                    }
+                })
+            }
+            None => response,
+        }
+    }
+}
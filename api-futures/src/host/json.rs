@@ -0,0 +1,248 @@
+//! # Json
+//!
+//! ## Reading json
+//!
+//! Request bodies in `hyper` are a stream of chunks that arrive
+//! asynchronously, so a handler that wants a json body needs to collect the
+//! stream before it can deserialise it.
+//! The `Json` type does this for any type that implements `Deserialize`.
+//!
+//! The body is buffered up to a limit, so a client can't exhaust the
+//! server's memory by sending an enormous request.
+//! Requests are rejected with:
+//!
+//! - `413 Payload Too Large` if the body is bigger than the limit
+//! - `415 Unsupported Media Type` if the `Content-Type` isn't `application/json`
+//! - `400 Bad Request` if the body isn't valid json for the type, along with
+//! the line and column the error was found at
+//!
+//! ## Examples
+//!
+//! ```
+//! # #![feature(associated_consts, proc_macro)]
+//! # #[macro_use]
+//! # extern crate serde_derive;
+//! # extern crate futures;
+//! # extern crate hyper;
+//! # extern crate webapp_demo;
+//! # use futures::Future;
+//! # use hyper::header::ContentLength;
+//! # use webapp_demo::host::*;
+//! #[derive(Deserialize)]
+//! struct Person {
+//!     name: String,
+//! }
+//!
+//! struct PersonHandler;
+//!
+//! impl Route for PersonHandler {
+//!     const ROUTE: &'static str = "/person";
+//! }
+//!
+//! impl Post for PersonHandler {
+//!     fn call(&self, _: Params, req: Request) -> HttpFuture {
+//!         Json::<Person>::from_request(req)
+//!             .map(|Json(person)| {
+//!                 let msg = format!("Hello {}", person.name);
+//!
+//!                 Response::new()
+//!                     .header(ContentLength(msg.len() as u64))
+//!                     .body(msg)
+//!             })
+//!             .into_http_future()
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! ## Writing json
+//!
+//! `Json` can also be converted into a `Response` for any type that
+//! implements `Serialize`, with the `Content-Type` and `Content-Length`
+//! headers set.
+//! The status code can be changed with `Json::status`, and the body can be
+//! pretty-printed with `Json::pretty`.
+//! Since it converts into a `Response`, a future of `Json` can be returned
+//! from a handler with `into_http_future`.
+//!
+//! ```
+//! # #![feature(associated_consts, proc_macro)]
+//! # #[macro_use]
+//! # extern crate serde_derive;
+//! # extern crate futures;
+//! # extern crate webapp_demo;
+//! # use futures::{Future, finished};
+//! # use webapp_demo::errors::Error;
+//! # use webapp_demo::host::*;
+//! #[derive(Serialize)]
+//! struct Person {
+//!     name: String,
+//! }
+//!
+//! struct PersonHandler;
+//!
+//! impl Route for PersonHandler {
+//!     const ROUTE: &'static str = "/person";
+//! }
+//!
+//! impl Get for PersonHandler {
+//!     fn call(&self, _: Params, _: Request) -> HttpFuture {
+//!         finished::<_, Error>(Person { name: "Ashley".to_owned() })
+//!             .map(|person| Json(person).pretty())
+//!             .into_http_future()
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+
+use futures::{Future, Stream, failed};
+use hyper::StatusCode;
+use hyper::header::{ContentLength, ContentType};
+use hyper::mime::{Mime, TopLevel, SubLevel};
+use serde::{Deserialize, Serialize};
+use serde_json;
+use errors::*;
+use super::{Request, Response};
+
+/// The default maximum size of a json request body, in bytes.
+pub const DEFAULT_BODY_LIMIT: usize = 1024 * 1024;
+
+/// A value that's read from, or written to, a json body.
+pub struct Json<T>(pub T);
+
+/// A future representing a json body that's being read.
+pub type JsonFuture<T> = Box<Future<Item = Json<T>, Error = Error>>;
+
+impl<T> Json<T>
+    where T: Deserialize + 'static
+{
+    /// Read and deserialise the body of a request.
+    ///
+    /// The body can be at most `DEFAULT_BODY_LIMIT` bytes.
+    pub fn from_request(req: Request) -> JsonFuture<T> {
+        Self::from_request_with_limit(req, DEFAULT_BODY_LIMIT)
+    }
+
+    /// Read and deserialise the body of a request that's at most `limit`
+    /// bytes.
+    pub fn from_request_with_limit(req: Request, limit: usize) -> JsonFuture<T> {
+        if !is_json(req.headers().get::<ContentType>()) {
+            let content_type = req.headers().get::<ContentType>().map(|c| c.to_string());
+
+            return box failed(ErrorKind::UnsupportedMediaType(content_type).into());
+        }
+
+        // Don't bother reading the body if we already know it's too big
+        if let Some(&ContentLength(len)) = req.headers().get::<ContentLength>() {
+            if len > limit as u64 {
+                return box failed(ErrorKind::BodyTooLarge(limit).into());
+            }
+        }
+
+        let body = req.body()
+            .map_err(Error::from)
+            .fold(Vec::new(), move |mut buf, chunk| -> Result<Vec<u8>> {
+                // The length header can't be trusted, so check as we go
+                if buf.len() + chunk.len() > limit {
+                    bail!(ErrorKind::BodyTooLarge(limit));
+                }
+
+                buf.extend_from_slice(&chunk);
+
+                Ok(buf)
+            });
+
+        box body.and_then(|buf| {
+            serde_json::from_slice(&buf)
+                .map(Json)
+                .map_err(|e| ErrorKind::InvalidJson(e.to_string()).into())
+        })
+    }
+}
+
+impl<T> Json<T>
+    where T: Serialize
+{
+    /// Respond with the given status code instead of `200 OK`.
+    pub fn status(self, status: StatusCode) -> JsonResponse<T> {
+        JsonResponse::from(self).status(status)
+    }
+
+    /// Pretty-print the json body.
+    pub fn pretty(self) -> JsonResponse<T> {
+        JsonResponse::from(self).pretty()
+    }
+}
+
+impl<T> From<Json<T>> for Response
+    where T: Serialize
+{
+    fn from(json: Json<T>) -> Response {
+        JsonResponse::from(json).into()
+    }
+}
+
+/// A json response with a custom status code or formatting.
+pub struct JsonResponse<T> {
+    value: T,
+    status: StatusCode,
+    pretty: bool,
+}
+
+impl<T> JsonResponse<T>
+    where T: Serialize
+{
+    /// Respond with the given status code instead of `200 OK`.
+    pub fn status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Pretty-print the json body.
+    pub fn pretty(mut self) -> Self {
+        self.pretty = true;
+        self
+    }
+}
+
+impl<T> From<Json<T>> for JsonResponse<T> {
+    fn from(json: Json<T>) -> Self {
+        JsonResponse {
+            value: json.0,
+            status: StatusCode::Ok,
+            pretty: false,
+        }
+    }
+}
+
+impl<T> From<JsonResponse<T>> for Response
+    where T: Serialize
+{
+    fn from(json: JsonResponse<T>) -> Response {
+        let body = if json.pretty {
+            serde_json::to_vec_pretty(&json.value)
+        } else {
+            serde_json::to_vec(&json.value)
+        };
+
+        match body {
+            Ok(body) => {
+                Response::new()
+                    .status(json.status)
+                    .header(ContentType::json())
+                    .header(ContentLength(body.len() as u64))
+                    .body(body)
+            }
+            // The value couldn't be serialised, which is a bug in the
+            // handler rather than something the client did
+            Err(_) => Response::new().status(StatusCode::InternalServerError),
+        }
+    }
+}
+
+fn is_json(content_type: Option<&ContentType>) -> bool {
+    match content_type {
+        Some(&ContentType(Mime(TopLevel::Application, SubLevel::Json, _))) => true,
+        _ => false,
+    }
+}
@@ -0,0 +1,407 @@
+//! # Concurrency limits
+//!
+//! Requests on the reactor are cheap, but the work they kick off, like jobs
+//! on a `CpuPool`, might not be.
+//! Without a limit, a burst of requests piles up as much work as clients
+//! can send, and every request gets slower until they all time out.
+//!
+//! The `ConcurrencyLimit` middleware bounds the number of requests that
+//! are in flight at once.
+//! When the limit is reached, new requests wait in a queue for one of the
+//! in-flight requests to finish.
+//! When the queue is full too, new requests are shed with a
+//! `503 Service Unavailable` and a `Retry-After` header, so clients and
+//! load balancers know to back off.
+//!
+//! A limit can be added for the whole router with
+//! `RouterBuilder::concurrency_limit`, or for a single route with
+//! `RouterBuilder::route_concurrency_limit`.
+//! Queued requests don't have a deadline of their own, so a timeout added
+//! before the limit also covers the time spent waiting in the queue.
+//!
+//! The number of requests in flight and queued for each limit are exported
+//! as the `http_requests_in_flight` and `http_requests_queued` metrics,
+//! labelled with the limit's name.
+//!
+//! ## Examples
+//!
+//! ```
+//! # #![feature(associated_consts)]
+//! # extern crate webapp_demo;
+//! # use std::time::Duration;
+//! # use webapp_demo::host::*;
+//! # struct ReportHandler;
+//! # impl Route for ReportHandler {
+//! # const ROUTE: &'static str = "/report";
+//! # }
+//! # impl Post for ReportHandler {
+//! # fn call(&self, _: Params, _: Request) -> HttpFuture {
+//! # unimplemented!()
+//! # }
+//! # }
+//! # fn main() {
+//! let router = RouterBuilder::new()
+//!     .post(ReportHandler)
+//!     .timeout(Duration::from_secs(30))
+//!     .concurrency_limit(1024, 256)
+//!     .route_concurrency_limit("/report", 4, 16)
+//!     .build()
+//!     .unwrap();
+//! # }
+//! ```
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use futures::{finished, oneshot, Complete, Future};
+use errors::*;
+use super::{HttpFuture, Request, Response};
+use super::metrics;
+use super::middleware::{Middleware, Next};
+
+/// The name used in metrics for a limit that isn't given one.
+pub const ROUTER_LIMIT: &'static str = "<router>";
+
+/// The default time clients are asked to wait before retrying a request
+/// that was shed.
+const DEFAULT_RETRY_AFTER_SECS: u64 = 1;
+
+/// A middleware that bounds the number of requests handled at once.
+///
+/// Clones of a limit share the same counts, so a clone can be kept to
+/// check on a limit after it's been added to a router.
+#[derive(Clone)]
+pub struct ConcurrencyLimit {
+    state: Arc<LimitState>,
+}
+
+struct LimitState {
+    name: String,
+    max_in_flight: usize,
+    max_queued: usize,
+    retry_after: Duration,
+    counts: Mutex<Counts>,
+}
+
+struct Counts {
+    in_flight: usize,
+    queued: usize,
+    /// Requests waiting for a permit, in the order they arrived.
+    ///
+    /// A waiter is removed as soon as it's dropped, so the queue never
+    /// holds more than `max_queued` waiters.
+    waiters: VecDeque<(u64, Complete<Permit>)>,
+    next_waiter: u64,
+}
+
+/// Marks a single request as in flight until it's dropped.
+///
+/// When a permit is dropped it's handed to the next queued request, if
+/// there is one, instead of being given back.
+struct Permit(Arc<LimitState>);
+
+/// Marks a single request as queued until it's dropped.
+///
+/// If the request is dropped before it's given a permit, like when it
+/// times out or the client goes away, it's removed from the queue.
+struct Queued(Arc<LimitState>, u64);
+
+enum Acquire {
+    Permit(Permit),
+    Queued(Box<Future<Item = Permit, Error = ()>>),
+    Full,
+}
+
+impl ConcurrencyLimit {
+    /// Create a limit that allows `max_in_flight` requests to be handled at
+    /// once, with `max_queued` more waiting for their turn.
+    ///
+    /// This fails if `max_in_flight` is zero, because no request would ever
+    /// get to run.
+    pub fn new(max_in_flight: usize, max_queued: usize) -> Result<Self> {
+        if max_in_flight == 0 {
+            bail!(ErrorKind::NoConcurrency);
+        }
+
+        Ok(ConcurrencyLimit {
+            state: Arc::new(LimitState {
+                name: ROUTER_LIMIT.to_owned(),
+                max_in_flight: max_in_flight,
+                max_queued: max_queued,
+                retry_after: Duration::from_secs(DEFAULT_RETRY_AFTER_SECS),
+                counts: Mutex::new(Counts {
+                    in_flight: 0,
+                    queued: 0,
+                    waiters: VecDeque::new(),
+                    next_waiter: 0,
+                }),
+            }),
+        })
+    }
+
+    /// Set the name used to label this limit's metrics.
+    ///
+    /// This has to be set before the limit is cloned.
+    pub fn name(mut self, name: &str) -> Self {
+        Arc::get_mut(&mut self.state).expect("the limit has already been cloned").name = name.to_owned();
+
+        self
+    }
+
+    /// Set how long clients are asked to wait before retrying a request
+    /// that was shed.
+    ///
+    /// This has to be set before the limit is cloned.
+    pub fn retry_after(mut self, retry_after: Duration) -> Self {
+        Arc::get_mut(&mut self.state).expect("the limit has already been cloned").retry_after = retry_after;
+
+        self
+    }
+
+    /// The number of requests currently in flight.
+    pub fn in_flight(&self) -> usize {
+        self.state.counts().in_flight
+    }
+
+    /// The number of requests currently waiting to be handled.
+    pub fn queued(&self) -> usize {
+        self.state.counts().queued
+    }
+}
+
+impl Middleware for ConcurrencyLimit {
+    fn call(&self, req: Request, next: Next) -> HttpFuture {
+        match LimitState::acquire(&self.state) {
+            Acquire::Permit(permit) => call_with_permit(permit, req, next),
+            Acquire::Queued(permit) => {
+                let retry_after = self.state.retry_after;
+
+                box permit.then(move |permit| match permit {
+                    Ok(permit) => call_with_permit(permit, req, next),
+                    Err(_) => box finished(shed(retry_after)),
+                })
+            }
+            Acquire::Full => box finished(shed(self.state.retry_after)),
+        }
+    }
+}
+
+/// The response for a request that's turned away.
+fn shed(retry_after: Duration) -> Response {
+    ErrorKind::Overloaded(retry_after).into()
+}
+
+/// Call the rest of the chain, holding the permit until the response
+/// future completes or is dropped.
+fn call_with_permit(permit: Permit, req: Request, next: Next) -> HttpFuture {
+    box next.call(req).then(move |response| {
+        drop(permit);
+
+        response
+    })
+}
+
+impl LimitState {
+    /// Take a permit if one is free, otherwise join the queue if there's
+    /// room.
+    fn acquire(state: &Arc<LimitState>) -> Acquire {
+        let mut counts = state.counts();
+
+        if counts.in_flight < state.max_in_flight {
+            counts.in_flight += 1;
+            state.observe(&counts);
+
+            return Acquire::Permit(Permit(state.clone()));
+        }
+
+        if counts.queued < state.max_queued {
+            let (complete, permit) = oneshot();
+
+            let id = counts.next_waiter;
+            counts.next_waiter = counts.next_waiter.wrapping_add(1);
+
+            counts.queued += 1;
+            counts.waiters.push_back((id, complete));
+            state.observe(&counts);
+
+            let queued = Queued(state.clone(), id);
+
+            return Acquire::Queued(box permit.then(move |permit| {
+                drop(queued);
+
+                permit.map_err(|_| ())
+            }));
+        }
+
+        Acquire::Full
+    }
+
+    fn counts(&self) -> MutexGuard<Counts> {
+        // The lock is never held while calling out to other code, so it
+        // can't really be poisoned, but if it is the counts are still fine
+        self.counts.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn observe(&self, counts: &Counts) {
+        metrics::observe_concurrency(&self.name, counts.in_flight, counts.queued);
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let next = {
+            let mut counts = self.0.counts();
+
+            let next = counts.waiters.pop_front();
+
+            if next.is_none() {
+                counts.in_flight -= 1;
+                self.0.observe(&counts);
+            }
+
+            next
+        };
+
+        // The permit is handed over outside the lock, because if the waiter
+        // went away after it was taken off the queue then the permit is
+        // dropped again straight away
+        if let Some((_, next)) = next {
+            next.complete(Permit(self.0.clone()));
+        }
+    }
+}
+
+impl Drop for Queued {
+    fn drop(&mut self) {
+        let mut counts = self.0.counts();
+
+        // The waiter is only still queued if it never got a permit
+        let id = self.1;
+        if let Some(index) = counts.waiters.iter().position(|&(waiter, _)| waiter == id) {
+            counts.waiters.remove(index);
+        }
+
+        counts.queued -= 1;
+        self.0.observe(&counts);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use futures::Future;
+    use hyper::StatusCode;
+    use errors::ErrorKind;
+    use super::*;
+
+    fn acquire(limit: &ConcurrencyLimit) -> Acquire {
+        LimitState::acquire(&limit.state)
+    }
+
+    fn permit(limit: &ConcurrencyLimit) -> Permit {
+        match acquire(limit) {
+            Acquire::Permit(permit) => permit,
+            _ => panic!("expected a permit"),
+        }
+    }
+
+    fn queued(limit: &ConcurrencyLimit) -> Box<Future<Item = Permit, Error = ()>> {
+        match acquire(limit) {
+            Acquire::Queued(permit) => permit,
+            _ => panic!("expected to be queued"),
+        }
+    }
+
+    #[test]
+    fn zero_in_flight_is_err() {
+        match ConcurrencyLimit::new(0, 1) {
+            Ok(_) => panic!("expected an error"),
+            Err(e) => {
+                match e.0 {
+                    ErrorKind::NoConcurrency => (),
+                    kind => panic!("unexpected error: {:?}", kind),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn permit_is_handed_to_queued_request() {
+        let limit = ConcurrencyLimit::new(1, 1).unwrap();
+
+        let first = permit(&limit);
+        let second = queued(&limit);
+
+        assert_eq!(1, limit.in_flight());
+        assert_eq!(1, limit.queued());
+
+        drop(first);
+
+        let second = second.wait().unwrap();
+
+        assert_eq!(1, limit.in_flight());
+        assert_eq!(0, limit.queued());
+
+        drop(second);
+
+        assert_eq!(0, limit.in_flight());
+    }
+
+    #[test]
+    fn requests_are_shed_when_queue_is_full() {
+        let limit = ConcurrencyLimit::new(1, 1).unwrap();
+
+        let _first = permit(&limit);
+        let _second = queued(&limit);
+
+        match acquire(&limit) {
+            Acquire::Full => (),
+            _ => panic!("expected to be shed"),
+        }
+
+        let response = shed(Duration::from_millis(1500));
+
+        assert_eq!(StatusCode::ServiceUnavailable, response.status_code());
+        assert_eq!(Some(&[b"2".to_vec()][..]), response.headers().get_raw("Retry-After"));
+    }
+
+    #[test]
+    fn cancelled_requests_leave_the_queue() {
+        let limit = ConcurrencyLimit::new(1, 1).unwrap();
+
+        let first = permit(&limit);
+
+        for _ in 0..1000 {
+            drop(queued(&limit));
+
+            assert_eq!(0, limit.queued());
+            assert_eq!(0, limit.state.counts().waiters.len());
+        }
+
+        let waiting = queued(&limit);
+
+        drop(first);
+
+        drop(waiting.wait().unwrap());
+
+        assert_eq!(0, limit.in_flight());
+        assert_eq!(0, limit.queued());
+    }
+
+    #[test]
+    fn permit_is_returned_if_waiter_is_gone() {
+        let limit = ConcurrencyLimit::new(1, 2).unwrap();
+
+        let first = permit(&limit);
+        let second = queued(&limit);
+        let third = queued(&limit);
+
+        drop(second);
+        drop(first);
+
+        drop(third.wait().unwrap());
+
+        assert_eq!(0, limit.in_flight());
+        assert_eq!(0, limit.queued());
+    }
+}
@@ -0,0 +1,166 @@
+//! # Metrics
+//!
+//! The router records a counter and a latency histogram for every request
+//! it handles, labelled by the matched `Route::ROUTE` pattern, the HTTP
+//! method and the response status code.
+//! Using the route pattern rather than the raw path keeps the number of
+//! label values small; `/person/1` and `/person/2` both count towards
+//! `/person/:id`.
+//! For the same reason, requests with non-standard methods are all
+//! labelled with a method of `other`.
+//!
+//! Routers with a `ConcurrencyLimit` also record gauges for the number of
+//! requests in flight and queued, labelled by the name of the limit.
+//!
+//! The collected metrics can be served in the Prometheus text format by
+//! registering the `Metrics` handler:
+//!
+//! ```
+//! # #![feature(associated_consts)]
+//! # extern crate webapp_demo;
+//! # use webapp_demo::host::*;
+//! # fn main() {
+//! let router = RouterBuilder::new()
+//!     .get(Metrics)
+//!     .build()
+//!     .unwrap();
+//! # }
+//! ```
+
+use std::time::{Duration, Instant};
+use futures::{finished, Future};
+use hyper::Method;
+use hyper::header::{ContentLength, ContentType};
+use prometheus::{self, CounterVec, Encoder, GaugeVec, HistogramOpts, HistogramVec, Opts, TextEncoder};
+use super::{HttpFuture, Get, Params, Request, Response, Route, StatusCode};
+
+/// The route label used for requests that didn't match any handler.
+pub const UNMATCHED: &'static str = "<unmatched>";
+
+/// The method label used for requests with non-standard methods.
+pub const OTHER_METHOD: &'static str = "other";
+
+lazy_static! {
+    static ref HTTP_REQUESTS_TOTAL: CounterVec = {
+        let counter = CounterVec::new(Opts::new("http_requests_total",
+                                                "Total number of HTTP requests handled."),
+                                      &["route", "method", "status"])
+            .unwrap();
+
+        prometheus::register(Box::new(counter.clone())).unwrap();
+
+        counter
+    };
+
+    static ref HTTP_REQUEST_DURATION_SECONDS: HistogramVec = {
+        let histogram = HistogramVec::new(HistogramOpts::new("http_request_duration_seconds",
+                                                             "HTTP request latency in seconds."),
+                                          &["route", "method", "status"])
+            .unwrap();
+
+        prometheus::register(Box::new(histogram.clone())).unwrap();
+
+        histogram
+    };
+
+    static ref HTTP_REQUESTS_IN_FLIGHT: GaugeVec = {
+        let gauge = GaugeVec::new(Opts::new("http_requests_in_flight",
+                                            "Number of HTTP requests currently being handled."),
+                                  &["limit"])
+            .unwrap();
+
+        prometheus::register(Box::new(gauge.clone())).unwrap();
+
+        gauge
+    };
+
+    static ref HTTP_REQUESTS_QUEUED: GaugeVec = {
+        let gauge = GaugeVec::new(Opts::new("http_requests_queued",
+                                            "Number of HTTP requests waiting to be handled."),
+                                  &["limit"])
+            .unwrap();
+
+        prometheus::register(Box::new(gauge.clone())).unwrap();
+
+        gauge
+    };
+}
+
+/// Record the outcome of a request once its response future completes.
+///
+/// A future that fails with a `hyper::Error` is recorded with a status of
+/// `error`, because no response was ever produced for it.
+pub fn instrument(route: String,
+                  method: Method,
+                  start: Instant,
+                  response: HttpFuture)
+                  -> HttpFuture {
+    box response.then(move |response| {
+        let status = match response {
+            Ok(ref response) => response.status_code().to_u16().to_string(),
+            Err(_) => "error".to_owned(),
+        };
+
+        observe(&route, &method_label(&method), &status, start.elapsed());
+
+        response
+    })
+}
+
+/// The method label for a request.
+///
+/// Extension methods are all labelled `other`, so clients can't create
+/// new series by sending made up methods.
+fn method_label(method: &Method) -> String {
+    match *method {
+        Method::Extension(_) => OTHER_METHOD.to_owned(),
+        ref method => method.to_string(),
+    }
+}
+
+fn observe(route: &str, method: &str, status: &str, elapsed: Duration) {
+    let labels = [route, method, status];
+
+    HTTP_REQUESTS_TOTAL.with_label_values(&labels).inc();
+    HTTP_REQUEST_DURATION_SECONDS.with_label_values(&labels).observe(as_secs(elapsed));
+}
+
+/// Record the current counts for a concurrency limit.
+pub fn observe_concurrency(limit: &str, in_flight: usize, queued: usize) {
+    HTTP_REQUESTS_IN_FLIGHT.with_label_values(&[limit]).set(in_flight as f64);
+    HTTP_REQUESTS_QUEUED.with_label_values(&[limit]).set(queued as f64);
+}
+
+fn as_secs(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1_000_000_000f64
+}
+
+/// A handler that serves the collected metrics.
+///
+/// Metrics are rendered in the Prometheus text exposition format.
+pub struct Metrics;
+
+impl Route for Metrics {
+    const ROUTE: &'static str = "/metrics";
+}
+
+// 'GET /metrics'
+impl Get for Metrics {
+    fn call(&self, _: Params, _: Request) -> HttpFuture {
+        let encoder = TextEncoder::new();
+        let metric_families = prometheus::gather();
+
+        let mut buffer = Vec::new();
+        let response = match encoder.encode(&metric_families, &mut buffer) {
+            Ok(()) => {
+                Response::new()
+                    .header(ContentType(encoder.format_type().parse().unwrap()))
+                    .header(ContentLength(buffer.len() as u64))
+                    .body(buffer)
+            }
+            Err(_) => Response::new().status(StatusCode::InternalServerError),
+        };
+
+        box finished(response)
+    }
+}
@@ -0,0 +1,138 @@
+//! # Middleware
+//!
+//! Middleware runs around request handlers, so cross-cutting concerns like
+//! logging, authentication, CORS and timeouts can be written once instead
+//! of in every handler.
+//!
+//! A middleware is given the request along with `Next`, which is the rest
+//! of the chain, ending in the handler itself.
+//! That gives it a few options:
+//!
+//! - do something before the handler runs, by looking at the request
+//! before calling `next`
+//! - do something after the handler runs, by mapping the future returned
+//! from `next`
+//! - short-circuit the handler, by returning a response without calling
+//! `next` at all
+//!
+//! Middleware can be added to the whole router with `RouterBuilder::middleware`,
+//! or to a single route pattern with `RouterBuilder::route_middleware`.
+//! Router middleware runs for every request, including ones that don't match
+//! a route, and runs before any route middleware.
+//! Middleware runs in the order it's added.
+//!
+//! ## Examples
+//!
+//! A middleware that logs the status of each response:
+//!
+//! ```
+//! # #![feature(box_syntax)]
+//! # extern crate futures;
+//! # extern crate webapp_demo;
+//! # use futures::Future;
+//! # use webapp_demo::host::*;
+//! struct Log;
+//!
+//! impl Middleware for Log {
+//!     fn call(&self, req: Request, next: Next) -> HttpFuture {
+//!         let path = req.path().unwrap_or("").to_owned();
+//!
+//!         box next.call(req).map(move |res| {
+//!             println!("{} -> {}", path, res.status_code());
+//!
+//!             res
+//!         })
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! Closures can be used as middleware too.
+//! Here's one that rejects requests without an `Authorization` header
+//! before they get to the handler:
+//!
+//! ```
+//! # #![feature(box_syntax)]
+//! # extern crate futures;
+//! # extern crate hyper;
+//! # extern crate webapp_demo;
+//! # use futures::finished;
+//! # use hyper::header::Authorization;
+//! # use webapp_demo::host::*;
+//! # struct AdminHandler;
+//! # impl Get for AdminHandler {
+//! # fn call(&self, _: Params, _: Request) -> HttpFuture {
+//! # unimplemented!()
+//! # }
+//! # }
+//! # fn main() {
+//! let router = RouterBuilder::new()
+//!     .get_at("/admin/*path", AdminHandler)
+//!     .route_middleware("/admin/*path", |req: Request, next: Next| -> HttpFuture {
+//!         if req.headers().has::<Authorization<String>>() {
+//!             next.call(req)
+//!         } else {
+//!             box finished(Response::new().status(StatusCode::Unauthorized))
+//!         }
+//!     })
+//!     .build()
+//!     .unwrap();
+//! # }
+//! ```
+
+use std::boxed::FnBox;
+use std::sync::Arc;
+use super::{HttpFuture, Request};
+
+/// A middleware that runs around request handlers.
+pub trait Middleware
+    where Self: Send + Sync
+{
+    /// Call the middleware with the given request and the rest of the chain.
+    fn call(&self, req: Request, next: Next) -> HttpFuture;
+}
+
+impl<F> Middleware for F
+    where F: Fn(Request, Next) -> HttpFuture + Send + Sync
+{
+    fn call(&self, req: Request, next: Next) -> HttpFuture {
+        self(req, next)
+    }
+}
+
+/// A chain of middleware.
+pub type Chain = Arc<Vec<Arc<Middleware>>>;
+
+/// The rest of a middleware chain.
+///
+/// `Next` owns everything it needs, so it can be held on to and called
+/// later, like after waiting for some other future.
+pub struct Next {
+    chain: Chain,
+    index: usize,
+    endpoint: Box<FnBox(Request) -> HttpFuture>,
+}
+
+impl Next {
+    /// Create a chain that runs the given middleware before calling the
+    /// endpoint.
+    pub fn new(chain: Chain, endpoint: Box<FnBox(Request) -> HttpFuture>) -> Self {
+        Next {
+            chain: chain,
+            index: 0,
+            endpoint: endpoint,
+        }
+    }
+
+    /// Call the rest of the chain.
+    pub fn call(mut self, req: Request) -> HttpFuture {
+        match self.chain.get(self.index).cloned() {
+            Some(middleware) => {
+                self.index += 1;
+
+                middleware.call(req, self)
+            }
+            None => (self.endpoint)(req),
+        }
+    }
+}
@@ -13,9 +13,26 @@
 //! - `Route` for a url path pattern to match
 //! - `Get` for handling a `GET` request
 //! - `Post` for handling a `POST` request
+//! - `Put` for handling a `PUT` request
+//! - `Delete` for handling a `DELETE` request
+//! - `Patch` for handling a `PATCH` request
+//! - `Head` for handling a `HEAD` request
+//! - `Options` for handling an `OPTIONS` request
 //!
 //! Requests can only be routed to a handler that implements `Route` and at least
-//! one of `Get` and `Post`.
+//! one of the method traits.
+//!
+//! A `HEAD` request for a route that doesn't have a `Head` handler is answered
+//! by its `Get` handler instead, with the response body stripped.
+//! Likewise, an `OPTIONS` request for a route that doesn't have an `Options`
+//! handler is answered with an `Allow` header listing the methods that
+//! do have handlers.
+//!
+//! A request for a path that doesn't match any route gets a `404 Not Found`.
+//! A request for a path that does match a route, but not for the request's
+//! method, gets a `405 Method Not Allowed` with an `Allow` header.
+//! These responses can be replaced by adding fallback handlers with
+//! `RouterBuilder::not_found` and `RouterBuilder::method_not_allowed`.
 //!
 //! ## Examples
 //!
@@ -74,8 +91,34 @@
 //! # fn main() {}
 //! ```
 //!
-//! Note that we can't use different routes for the same handler.
-//! This is enforced by Rust's type system.
+//! A handler's `Route` is a constant, so it's always added to the same route.
+//! To add a handler at a different route, or at more than one route, use
+//! `RouterBuilder::get_at` and friends instead:
+//!
+//! ```
+//! # #![feature(associated_consts)]
+//! # extern crate webapp_demo;
+//! # use std::sync::Arc;
+//! # use webapp_demo::host::*;
+//! # struct PersonHandler;
+//! # impl Get for PersonHandler {
+//! # fn call(&self, _: Params, _: Request) -> HttpFuture {
+//! # unimplemented!()
+//! # }
+//! # }
+//! # fn main() {
+//! let handler = Arc::new(PersonHandler);
+//!
+//! let router = RouterBuilder::new()
+//!     .get_at("/person/:id", handler.clone())
+//!     .get_at("/v1/person/:id", handler)
+//!     .build()
+//!     .unwrap();
+//! # }
+//! ```
+//!
+//! The method traits are implemented for `Arc<H>`, so a single handler can
+//! be shared between routes like this without being cloned.
 //!
 //! # Router
 //!
@@ -118,22 +161,170 @@
 //! let router = RouterBuilder::new()
 //!     .get(MyGetHandler)
 //!     .post(MyPostHandler)
-//!     .build();
+//!     .build()
+//!     .unwrap();
 //! # }
 //! ```
 //!
 //! The `get` and `post` methods expect a `T: Get + Route` and
 //! `T: Post + Route` respectively.
+//! There are matching `put`, `delete`, `patch`, `head` and `options` methods
+//! for the other method traits.
+//! Each of these has an `_at` variant, like `get_at`, that takes the route
+//! as an argument instead of needing `Route`.
+//!
+//! `build` checks the routes for conflicts, and fails if a route and method
+//! have more than one handler, or if a path could match more than one
+//! route for the same method.
+//!
+//! All handlers for a route pattern share a single entry in the router's
+//! table, so a request's path is matched once before its method is looked up.
+//! The table can be inspected with `Router::routes`, which lists every
+//! route along with the methods it supports.
+//!
+//! # Paths
+//!
+//! By default, paths are matched in their canonical form, without empty
+//! segments or a trailing slash, so `/person/1/` matches `/person/:id`.
+//! That can be changed with `RouterBuilder::path_policy`, to either only
+//! match paths exactly or to redirect clients to the canonical path.
+//! Parameters are always percent-decoded.
+//!
+//! # Typed parameters
+//!
+//! Handlers get the url parameters as a `Params` bucket by default.
+//! A method trait's `Params` associated type can be set to any type that
+//! implements `FromParams` instead, so parameters are parsed and validated
+//! before the handler is called.
+//! Requests with invalid parameters are answered with `400 Bad Request`.
+//!
+//! # Mounting routers
+//!
+//! Routers can be composed with `RouterBuilder::mount`, which adds all the
+//! routes of another `Router` under a path prefix.
+//! That way separate parts of the app can be built as their own routers and
+//! assembled in `main`:
+//!
+//! ```
+//! # #![feature(associated_consts)]
+//! # extern crate webapp_demo;
+//! # use webapp_demo::host::*;
+//! # struct PersonHandler;
+//! # impl Route for PersonHandler {
+//! # const ROUTE: &'static str = "/person/:id";
+//! # }
+//! # impl Get for PersonHandler {
+//! # fn call(&self, _: Params, _: Request) -> HttpFuture {
+//! # unimplemented!()
+//! # }
+//! # }
+//! # fn main() {
+//! let people = RouterBuilder::new()
+//!     .get(PersonHandler)
+//!     .build()
+//!     .unwrap();
+//!
+//! // Serves `GET /api/v1/person/:id`
+//! let router = RouterBuilder::new()
+//!     .mount("/api/v1", people)
+//!     .build()
+//!     .unwrap();
+//! # }
+//! ```
+//!
+//! Middleware added to the child router only runs for the child's routes.
+//!
+//! # Named routes
+//!
+//! Routes can be given names with `RouterBuilder::name`.
+//! Paths for named routes are built with `Router::url_for`, or from a
+//! handler using the `Urls` state, so links don't duplicate the route
+//! patterns.
+//!
+//! # Shared state
+//!
+//! State that's shared by every handler, like a database pool, is added to
+//! the router with `RouterBuilder::with_state`.
+//! Handlers can borrow it with `Params::state`, or ask for it as their
+//! parameters with the `State` extractor.
+//!
+//! # Query strings
+//!
+//! The query string of any request can be deserialised into a typed struct
+//! with `Query::from_request`.
+//! Repeated keys are collected into a `Vec`, and invalid values are
+//! answered with `400 Bad Request`.
+//!
+//! # Request bodies
+//!
+//! Handlers get the request body as an unread stream.
+//! A json body can be read and deserialised with `Json::from_request`,
+//! which limits the size of the body and checks its `Content-Type`.
+//!
+//! `Json` also converts into a `Response`, so handlers can respond with any
+//! serialisable value instead of building the body and headers themselves.
+//!
+//! # Middleware
+//!
+//! Cross-cutting logic, like logging or authentication, can be run around
+//! handlers by implementing the `Middleware` trait.
+//! Middleware is added to the router with `RouterBuilder::middleware` to run
+//! for every request, or with `RouterBuilder::route_middleware` to run for
+//! a single route.
+//!
+//! # OpenAPI
+//!
+//! Calling `RouterBuilder::openapi` serves an OpenAPI document describing
+//! every route at `GET /openapi.json`.
+//! Handlers can add summaries and schemas for their requests and responses
+//! by implementing the `describe` method on their method traits.
+//!
+//! # Timeouts
+//!
+//! The `Timeout` middleware fails requests that take too long with a
+//! `503 Service Unavailable`, cancelling their handlers.
+//! It can be added for every route with `RouterBuilder::timeout`, or for a
+//! single route with `RouterBuilder::route_timeout`.
+//!
+//! # Concurrency limits
+//!
+//! The `ConcurrencyLimit` middleware bounds the number of requests handled
+//! at once, queueing a few more and shedding the rest with a
+//! `503 Service Unavailable`.
+//! It can be added for every route with `RouterBuilder::concurrency_limit`,
+//! or for a single route with `RouterBuilder::route_concurrency_limit`.
+//!
+//! # Panics
+//!
+//! Panics in handlers and middleware are caught by the router, so the
+//! request gets a `500 Internal Server Error` instead of taking down every
+//! other connection on the reactor.
+//! Call `log_panics` at startup to log them with a backtrace.
+//!
+//! # Metrics
+//!
+//! Every request passing through the router is counted and timed.
+//! Register the `Metrics` handler to serve them at `GET /metrics` in the
+//! Prometheus text format.
+//!
+//! # Shutting down
+//!
+//! A `Router` can be wrapped in a `Drain` service, which counts the requests
+//! in flight so they can be allowed to finish before the server stops.
+//!
+//! # HTTPS
+//!
+//! `Server` only speaks plain HTTP.
+//! The `serve_tls` function listens for HTTPS connections instead, and can
+//! serve the same `Router` as a `Server`.
 
+use std::sync::Arc;
 use futures::{Future, Finished};
 use hyper::Error as HyperError;
 use errors::*;
-pub use hyper::StatusCode;
+pub use hyper::{Body, StatusCode};
 pub use hyper::server::{Server, Request, Response};
 
-/// A bucket of parameters matched in the url path.
-pub use route_recognizer::Params;
-
 /// A future representing a `Response`.
 ///
 /// This type is _boxed_, which means it's a pointer to some value on
@@ -162,25 +353,110 @@ pub trait Route {
     const ROUTE: &'static str;
 }
 
-/// A handler for a `GET` request.
-pub trait Get
-    where Self: Send + Sync
-{
-    /// Call the handler with the given url parameters and request.
-    fn call(&self, params: Params, req: Request) -> HttpFuture;
+/// Declare a method trait, and implement it for shared handlers.
+///
+/// Every method trait has the same shape, so adding a method is one more
+/// entry here and in `RouterBuilder`'s `handler_methods!`.
+macro_rules! method_trait {
+    ($($(#[$attr:meta])* trait $method:ident;)*) => {
+        $(
+            $(#[$attr])*
+            pub trait $method
+                where Self: Send + Sync
+            {
+                /// The url parameters the handler expects.
+                type Params: FromParams = Params;
+
+                /// Call the handler with the given url parameters and request.
+                fn call(&self, params: Self::Params, req: Request) -> HttpFuture;
+
+                /// Describe the handler for the OpenAPI document.
+                fn describe(&self) -> Operation {
+                    Operation::new()
+                }
+            }
+
+            impl<H> $method for Arc<H>
+                where H: $method
+            {
+                type Params = <H as $method>::Params;
+
+                fn call(&self, params: Self::Params, req: Request) -> HttpFuture {
+                    $method::call(&**self, params, req)
+                }
+
+                fn describe(&self) -> Operation {
+                    $method::describe(&**self)
+                }
+            }
+        )*
+    }
 }
 
-/// A handler for a `POST` request.
-pub trait Post
-    where Self: Send + Sync
-{
-    /// Call the handler with the given url parameters and request.
+method_trait! {
+    /// A handler for a `GET` request.
+    trait Get;
+    /// A handler for a `POST` request.
     ///
-    /// For posts we could also pre-buffer the request body and
-    /// pass it as a parameter to this method.
-    fn call(&self, params: Params, req: Request) -> HttpFuture;
+    /// The request body is a stream that hasn't been read yet.
+    /// Use `Json::from_request` to buffer and deserialise a json body.
+    trait Post;
+    /// A handler for a `PUT` request.
+    trait Put;
+    /// A handler for a `DELETE` request.
+    trait Delete;
+    /// A handler for a `PATCH` request.
+    trait Patch;
+    /// A handler for a `HEAD` request.
+    ///
+    /// Handlers don't usually need to implement `Head`, because the router
+    /// will answer a `HEAD` request using the `Get` handler for a route,
+    /// with the response body stripped.
+    /// Implementing `Head` is useful when the headers can be produced more
+    /// cheaply than the full response.
+    /// Any body on the returned response is sent to the client, so it
+    /// should be left empty.
+    trait Head;
+    /// A handler for an `OPTIONS` request.
+    trait Options;
+}
+
+impl<H> Route for Arc<H>
+    where H: Route
+{
+    const ROUTE: &'static str = H::ROUTE;
 }
 
+mod params;
+mod json;
+mod query;
+mod state;
+mod openapi;
+mod urls;
+mod path;
+mod fallback;
+mod timeout;
+mod limit;
+mod panic;
 mod router;
+mod middleware;
+mod metrics;
+mod shutdown;
+mod tls;
 
+pub use self::params::{Params, FromParams, param};
+pub use self::json::{Json, JsonFuture, JsonResponse, DEFAULT_BODY_LIMIT};
+pub use self::query::Query;
+pub use self::state::State;
+pub use self::openapi::{Operation, OPENAPI_ROUTE};
+pub use self::urls::Urls;
+pub use self::path::PathPolicy;
+pub use self::fallback::Fallback;
+pub use self::timeout::{Timeout, max_timeout};
+pub use self::limit::{ConcurrencyLimit, ROUTER_LIMIT};
+pub use self::panic::log_panics;
 pub use self::router::*;
+pub use self::middleware::{Middleware, Next};
+pub use self::metrics::Metrics;
+pub use self::shutdown::{Drain, DrainHandle};
+pub use self::tls::serve_tls;
@@ -0,0 +1,271 @@
+//! # OpenAPI
+//!
+//! The router knows every route and method it can handle, so it can
+//! describe the api as an [OpenAPI 3]() document.
+//! Calling `RouterBuilder::openapi` serves that document at
+//! `GET /openapi.json`.
+//!
+//! Path parameters are worked out from the route patterns themselves.
+//! Anything else, like a summary or the schemas of the request and response
+//! bodies, is up to the handler.
+//! Each of the method traits has a `describe` method that returns an
+//! `Operation`, which is empty by default.
+//!
+//! Schemas are [JSON Schema]() documents, given as a `serde_json::Value`.
+//!
+//! ## Examples
+//!
+//! ```
+//! # #![feature(associated_consts)]
+//! # extern crate serde_json;
+//! # extern crate webapp_demo;
+//! # use webapp_demo::host::*;
+//! struct PersonHandler;
+//!
+//! impl Route for PersonHandler {
+//!     const ROUTE: &'static str = "/person/:id";
+//! }
+//!
+//! impl Get for PersonHandler {
+//!     fn call(&self, _: Params, _: Request) -> HttpFuture {
+//!         // Get the person
+//! # unimplemented!()
+//!     }
+//!
+//!     fn describe(&self) -> Operation {
+//!         let person = serde_json::from_str(r#"{
+//!             "type": "object",
+//!             "properties": {
+//!                 "name": { "type": "string" }
+//!             }
+//!         }"#).unwrap();
+//!
+//!         Operation::new()
+//!             .summary("Get a person by their id")
+//!             .response(200, "The person", Some(person))
+//!             .response(404, "There's no person with that id", None)
+//!     }
+//! }
+//!
+//! # fn main() {
+//! let router = RouterBuilder::new()
+//!     .get(PersonHandler)
+//!     .openapi("People", "1.0.0")
+//!     .build()
+//!     .unwrap();
+//! # }
+//! ```
+
+use std::collections::BTreeMap;
+use hyper::Method;
+use serde_json::{self, Value};
+
+/// The route the OpenAPI document is served at.
+pub const OPENAPI_ROUTE: &'static str = "/openapi.json";
+
+/// A description of a handler for a single route and method.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Operation {
+    summary: Option<String>,
+    description: Option<String>,
+    request: Option<Value>,
+    responses: Vec<(u16, String, Option<Value>)>,
+}
+
+impl Operation {
+    /// Create an empty description.
+    pub fn new() -> Self {
+        Operation::default()
+    }
+
+    /// A short summary of what the handler does.
+    pub fn summary<S>(mut self, summary: S) -> Self
+        where S: Into<String>
+    {
+        self.summary = Some(summary.into());
+        self
+    }
+
+    /// A longer description of what the handler does.
+    pub fn description<S>(mut self, description: S) -> Self
+        where S: Into<String>
+    {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// The schema of the json request body.
+    pub fn request(mut self, schema: Value) -> Self {
+        self.request = Some(schema);
+        self
+    }
+
+    /// A response the handler can return, with the schema of its json body.
+    pub fn response<S>(mut self, status: u16, description: S, schema: Option<Value>) -> Self
+        where S: Into<String>
+    {
+        self.responses.push((status, description.into(), schema));
+        self
+    }
+}
+
+/// An OpenAPI document.
+#[derive(Serialize)]
+pub struct Document {
+    openapi: &'static str,
+    info: Info,
+    paths: BTreeMap<String, BTreeMap<String, OperationDoc>>,
+}
+
+#[derive(Serialize)]
+struct Info {
+    title: String,
+    version: String,
+}
+
+#[derive(Serialize)]
+struct OperationDoc {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    parameters: Vec<ParameterDoc>,
+    #[serde(rename = "requestBody", skip_serializing_if = "Option::is_none")]
+    request_body: Option<ContentDoc>,
+    responses: BTreeMap<String, ResponseDoc>,
+}
+
+#[derive(Serialize)]
+struct ParameterDoc {
+    name: String,
+    #[serde(rename = "in")]
+    location: &'static str,
+    required: bool,
+    schema: Value,
+}
+
+#[derive(Serialize)]
+struct ContentDoc {
+    content: BTreeMap<&'static str, MediaTypeDoc>,
+}
+
+#[derive(Serialize)]
+struct MediaTypeDoc {
+    schema: Value,
+}
+
+#[derive(Serialize)]
+struct ResponseDoc {
+    description: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<BTreeMap<&'static str, MediaTypeDoc>>,
+}
+
+impl Document {
+    /// Create a document with no paths.
+    pub fn new(title: &str, version: &str) -> Self {
+        Document {
+            openapi: "3.0.0",
+            info: Info {
+                title: title.to_owned(),
+                version: version.to_owned(),
+            },
+            paths: BTreeMap::new(),
+        }
+    }
+
+    /// Add an operation for a route pattern and method.
+    pub fn add(&mut self, route: &str, method: &Method, operation: &Operation) {
+        let parameters = params(route)
+            .into_iter()
+            .map(|name| {
+                ParameterDoc {
+                    name: name.to_owned(),
+                    location: "path",
+                    required: true,
+                    schema: string_schema(),
+                }
+            })
+            .collect();
+
+        let mut responses: BTreeMap<String, ResponseDoc> = operation.responses
+            .iter()
+            .map(|&(status, ref description, ref schema)| {
+                let response = ResponseDoc {
+                    description: description.clone(),
+                    content: schema.clone().map(json_content),
+                };
+
+                (status.to_string(), response)
+            })
+            .collect();
+
+        // Every operation needs at least one response
+        if responses.is_empty() {
+            responses.insert("default".to_owned(),
+                             ResponseDoc {
+                                 description: "The response".to_owned(),
+                                 content: None,
+                             });
+        }
+
+        let doc = OperationDoc {
+            summary: operation.summary.clone(),
+            description: operation.description.clone(),
+            parameters: parameters,
+            request_body: operation.request.clone().map(|schema| ContentDoc { content: json_content(schema) }),
+            responses: responses,
+        };
+
+        self.paths
+            .entry(path(route))
+            .or_insert_with(BTreeMap::new)
+            .insert(method.to_string().to_lowercase(), doc);
+    }
+
+    /// Serialise the document as json.
+    pub fn to_json(&self) -> Vec<u8> {
+        serde_json::to_vec_pretty(self).expect("the OpenAPI document should always serialise")
+    }
+}
+
+/// Convert a route pattern into an OpenAPI path, like `/person/:id` into
+/// `/person/{id}`.
+fn path(route: &str) -> String {
+    let path: Vec<String> = route.split('/')
+        .map(|segment| match param(segment) {
+            Some(name) => format!("{{{}}}", name),
+            None => segment.to_owned(),
+        })
+        .collect();
+
+    path.join("/")
+}
+
+/// Get the names of the parameters in a route pattern.
+fn params(route: &str) -> Vec<&str> {
+    route.split('/').filter_map(param).collect()
+}
+
+fn param(segment: &str) -> Option<&str> {
+    if segment.starts_with(':') || segment.starts_with('*') {
+        Some(&segment[1..])
+    } else {
+        None
+    }
+}
+
+fn string_schema() -> Value {
+    let mut schema = BTreeMap::new();
+    schema.insert("type".to_owned(), Value::String("string".to_owned()));
+
+    Value::Object(schema)
+}
+
+fn json_content(schema: Value) -> BTreeMap<&'static str, MediaTypeDoc> {
+    let mut content = BTreeMap::new();
+    content.insert("application/json", MediaTypeDoc { schema: schema });
+
+    content
+}
@@ -0,0 +1,83 @@
+//! # Panics
+//!
+//! All of the requests on a reactor share a single thread, so a handler
+//! that panics would take down every other connection along with it.
+//! To stop that from happening, the router catches panics from handlers
+//! and their middleware, both while the response future is being built and
+//! while it's being polled.
+//! A request that panics gets a `500 Internal Server Error`, and the
+//! reactor carries on serving other requests.
+//!
+//! Catching a panic doesn't stop it from being reported by the panic hook.
+//! Call `log_panics` at startup to install a hook that logs the panic along
+//! with a backtrace.
+
+use std::any::Any;
+use std::io::{self, Write};
+use std::panic::{self, AssertUnwindSafe};
+use backtrace::Backtrace;
+use futures::{finished, Async, Future, Poll};
+use hyper;
+use errors::*;
+use super::{HttpFuture, Response};
+
+/// Build and poll a response future, catching any panics.
+pub fn catch_panics<F>(respond: F) -> HttpFuture
+    where F: FnOnce() -> HttpFuture
+{
+    // The future is dropped after a panic, so it's never used again while
+    // it might be broken
+    match panic::catch_unwind(AssertUnwindSafe(respond)) {
+        Ok(response) => box CatchUnwind(response),
+        Err(_) => box finished(ErrorKind::HandlerPanicked.into()),
+    }
+}
+
+/// A future that responds with an error if polling it panics.
+struct CatchUnwind(HttpFuture);
+
+impl Future for CatchUnwind {
+    type Item = Response;
+    type Error = hyper::Error;
+
+    fn poll(&mut self) -> Poll<Response, hyper::Error> {
+        let inner = &mut self.0;
+
+        match panic::catch_unwind(AssertUnwindSafe(|| inner.poll())) {
+            Ok(poll) => poll,
+            Err(_) => Ok(Async::Ready(ErrorKind::HandlerPanicked.into())),
+        }
+    }
+}
+
+/// Log panics to `stderr` along with a backtrace.
+///
+/// This replaces any existing panic hook, so it should be called once at
+/// startup.
+pub fn log_panics() {
+    panic::set_hook(box |info| {
+        let msg = message(info.payload());
+
+        let location = match info.location() {
+            Some(location) => format!("{}:{}", location.file(), location.line()),
+            None => "an unknown location".to_owned(),
+        };
+
+        let _ = writeln!(io::stderr(),
+                         "panicked at '{}', {}\n{:?}",
+                         msg,
+                         location,
+                         Backtrace::new());
+    });
+}
+
+/// Get the message from a panic payload.
+fn message(payload: &(Any + Send)) -> &str {
+    if let Some(msg) = payload.downcast_ref::<&'static str>() {
+        msg
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg
+    } else {
+        "Box<Any>"
+    }
+}
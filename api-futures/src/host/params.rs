@@ -0,0 +1,207 @@
+//! # Typed url parameters
+//!
+//! Handlers are given the parameters matched in the url path as a bucket
+//! of strings by default.
+//! That means each handler has to find and validate the parameters it
+//! needs itself.
+//!
+//! Instead, a handler can declare a type for its parameters using the
+//! `Params` associated type on its method trait.
+//! That type implements `FromParams`, and the router uses it to parse the
+//! parameters before the handler is called.
+//! If the parameters can't be parsed then the router responds with a
+//! `400 Bad Request` naming the parameter, and the handler isn't called
+//! at all.
+//!
+//! The `from_params!` macro implements `FromParams` for a struct whose
+//! fields implement [`TryFrom<&str>`](), so each field is parsed from the
+//! url parameter with the same name.
+//!
+//! ## Examples
+//!
+//! ```
+//! # #![feature(associated_consts, try_from)]
+//! # #[macro_use]
+//! # extern crate webapp_demo;
+//! # use std::convert::TryFrom;
+//! # use webapp_demo::host::*;
+//! struct Id(String);
+//!
+//! impl<'a> TryFrom<&'a str> for Id {
+//!     type Err = &'static str;
+//!
+//!     fn try_from(id: &'a str) -> ::std::result::Result<Id, Self::Err> {
+//!         match id {
+//!             "" => Err("the id can't be empty"),
+//!             _ => Ok(Id(id.to_owned())),
+//!         }
+//!     }
+//! }
+//!
+//! struct PersonPath {
+//!     id: Id,
+//! }
+//!
+//! from_params!(PersonPath { id });
+//!
+//! struct PersonHandler;
+//!
+//! impl Route for PersonHandler {
+//!     const ROUTE: &'static str = "/person/:id";
+//! }
+//!
+//! impl Get for PersonHandler {
+//!     type Params = PersonPath;
+//!
+//!     fn call(&self, path: PersonPath, _: Request) -> HttpFuture {
+//!         let id = path.id;
+//!
+//!         // Get the person
+//! # unimplemented!()
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt::Display;
+use route_recognizer;
+use errors::*;
+use super::{HttpFuture, Request};
+use super::path;
+use super::state::{State, States};
+
+/// A bucket of parameters matched in the url path.
+///
+/// `Params` also gives access to any state added to the router with
+/// `RouterBuilder::with_state`.
+#[derive(Clone)]
+pub struct Params {
+    params: BTreeMap<String, String>,
+    state: States,
+}
+
+impl Params {
+    /// Find the value of a url parameter.
+    pub fn find(&self, key: &str) -> Option<&str> {
+        self.params.get(key).map(|value| &value[..])
+    }
+
+    /// Borrow the shared state of type `S`.
+    ///
+    /// Returns `None` if there's no state of that type.
+    pub fn state<S>(&self) -> Option<&S>
+        where S: Any + Send + Sync
+    {
+        self.state.get::<S>().map(|state| &**state)
+    }
+}
+
+/// Create `Params` from the url parameters of a matched route.
+///
+/// The values of the parameters are percent-decoded.
+pub fn from_route(params: route_recognizer::Params, state: States) -> Params {
+    Params {
+        params: params.iter().map(|(key, value)| (key.to_owned(), path::decode(value))).collect(),
+        state: state,
+    }
+}
+
+/// A type that can be parsed from the parameters matched in a url path.
+pub trait FromParams
+    where Self: Sized
+{
+    /// Parse the parameters.
+    ///
+    /// If the parameters are invalid then the error should be an
+    /// `ErrorKind::InvalidParam` naming the one that failed.
+    fn from_params(params: Params) -> Result<Self>;
+}
+
+impl FromParams for Params {
+    fn from_params(params: Params) -> Result<Self> {
+        Ok(params)
+    }
+}
+
+impl<S> FromParams for State<S>
+    where S: Any + Send + Sync
+{
+    fn from_params(params: Params) -> Result<Self> {
+        match params.state.get::<S>() {
+            Some(state) => Ok(State(state.clone())),
+            None => bail!(ErrorKind::StateNotFound),
+        }
+    }
+}
+
+impl<A, B> FromParams for (A, B)
+    where A: FromParams,
+          B: FromParams
+{
+    fn from_params(params: Params) -> Result<Self> {
+        Ok((A::from_params(params.clone())?, B::from_params(params)?))
+    }
+}
+
+/// Parse a single named parameter using its `TryFrom<&str>` implementation.
+///
+/// This is used by the `from_params!` macro, but can also be used to
+/// implement `FromParams` by hand.
+pub fn param<T>(params: &Params, name: &str) -> Result<T>
+    where T: for<'a> TryFrom<&'a str>,
+          for<'a> <T as TryFrom<&'a str>>::Err: Display
+{
+    let value = params.find(name)
+        .ok_or_else(|| Error::from(ErrorKind::InvalidParam(name.to_owned(), "the parameter is missing".to_owned())))?;
+
+    T::try_from(value).map_err(|e| ErrorKind::InvalidParam(name.to_owned(), e.to_string()).into())
+}
+
+/// The result of calling a handler.
+///
+/// If the handler couldn't be called then the error is returned along with
+/// the request, so the router can respond to it.
+pub type Handled = ::std::result::Result<HttpFuture, (Error, Request)>;
+
+/// Parse parameters and call a handler with them, or return the error if
+/// they're invalid.
+pub fn with_params<P, F>(params: Params, req: Request, call: F) -> Handled
+    where P: FromParams,
+          F: FnOnce(P, Request) -> HttpFuture
+{
+    match P::from_params(params) {
+        Ok(params) => Ok(call(params, req)),
+        Err(e) => Err((e, req)),
+    }
+}
+
+/// Implement `FromParams` for a struct.
+///
+/// Each of the listed fields is parsed from the url parameter with the same
+/// name, using its `TryFrom<&str>` implementation.
+///
+/// ```ignore
+/// struct PersonPath {
+///     id: Id,
+/// }
+///
+/// from_params!(PersonPath { id });
+/// ```
+#[macro_export]
+macro_rules! from_params {
+    ($name:ident { $($field:ident),* }) => (
+        impl $crate::host::FromParams for $name {
+            fn from_params(params: $crate::host::Params) -> $crate::errors::Result<Self> {
+                Ok($name {
+                    $($field: $crate::host::param(&params, stringify!($field))?,)*
+                })
+            }
+        }
+    );
+    ($name:ident { $($field:ident,)* }) => (
+        from_params!($name { $($field),* });
+    );
+}
@@ -0,0 +1,109 @@
+//! # Paths
+//!
+//! Clients don't always send paths in the same shape as our route patterns.
+//! A path might have a trailing slash, like `/person/1/`, or empty
+//! segments, like `/person//1`.
+//! The `PathPolicy` for a router decides what to do about it:
+//!
+//! - `Strict` only matches paths exactly as they're sent
+//! - `MatchBoth` matches the canonical form of a path, so `/person/1/` and
+//! `/person//1` are both handled like `/person/1`
+//! - `Redirect` responds to a path that isn't canonical with a
+//! `308 Permanent Redirect` to the canonical path, if it matches a route
+//!
+//! The canonical form of a path has no empty segments and no trailing slash,
+//! except for the root path `/`.
+//!
+//! Whatever the policy, parameters are percent-decoded before they're given
+//! to handlers, so `/person/ashley%20mannix` has an `id` of
+//! `ashley mannix`.
+//!
+//! ## Examples
+//!
+//! ```
+//! # extern crate webapp_demo;
+//! # use webapp_demo::host::*;
+//! # fn main() {
+//! let router = RouterBuilder::new()
+//!     .path_policy(PathPolicy::Redirect)
+//!     .build()
+//!     .unwrap();
+//! # }
+//! ```
+
+use std::borrow::Cow;
+use url::percent_encoding::percent_decode;
+
+/// What to do with paths that aren't in their canonical form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathPolicy {
+    /// Only match paths exactly as they're sent.
+    Strict,
+    /// Match paths as if they were sent in their canonical form.
+    MatchBoth,
+    /// Redirect paths that aren't canonical to their canonical form.
+    Redirect,
+}
+
+impl Default for PathPolicy {
+    fn default() -> Self {
+        PathPolicy::MatchBoth
+    }
+}
+
+/// Get the canonical form of a path.
+///
+/// Empty segments are removed, along with any trailing slash.
+pub fn canonical(path: &str) -> Cow<str> {
+    let segments: Vec<&str> = path.split('/').filter(|segment| !segment.is_empty()).collect();
+
+    let canonical = format!("/{}", segments.join("/"));
+
+    if canonical == path {
+        Cow::Borrowed(path)
+    } else {
+        Cow::Owned(canonical)
+    }
+}
+
+/// Percent-decode a parameter matched in a path.
+///
+/// Any bytes that aren't valid UTF8 once decoded are replaced.
+pub fn decode(param: &str) -> String {
+    percent_decode(param.as_bytes()).decode_utf8_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use super::*;
+
+    #[test]
+    fn root_is_canonical() {
+        match canonical("/") {
+            Cow::Borrowed(path) => assert_eq!("/", path),
+            Cow::Owned(path) => panic!("expected `/` to be borrowed, got {}", path),
+        }
+    }
+
+    #[test]
+    fn canonical_path_is_borrowed() {
+        match canonical("/a/b") {
+            Cow::Borrowed(path) => assert_eq!("/a/b", path),
+            Cow::Owned(path) => panic!("expected `/a/b` to be borrowed, got {}", path),
+        }
+    }
+
+    #[test]
+    fn empty_segments_and_trailing_slash_are_removed() {
+        assert_eq!("/a/b", canonical("/a//b/"));
+        assert_eq!("/a", canonical("/a/"));
+        assert_eq!("/", canonical("//"));
+    }
+
+    #[test]
+    fn decode_percent_encoded_param() {
+        assert_eq!("ashley mannix", decode("ashley%20mannix"));
+        assert_eq!("a/b", decode("a%2Fb"));
+    }
+}
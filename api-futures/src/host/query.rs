@@ -0,0 +1,372 @@
+//! # Query strings
+//!
+//! The `Query` type deserialises the query string of a request into any
+//! type that implements `Deserialize`.
+//! Values are parsed according to the type of the field they're for, so
+//! `?page=2` can be read into a `u32`.
+//! A key can be repeated to collect its values into a `Vec`, like
+//! `?tag=a&tag=b`.
+//! Fields that are optional in the query string should be an `Option`, or
+//! use `#[serde(default)]`.
+//!
+//! If the query string can't be deserialised then the error is an
+//! `ErrorKind::InvalidQuery`, which is answered with `400 Bad Request`.
+//!
+//! `Query::from_request` only borrows the request, so it can be used from a
+//! handler for any method.
+//!
+//! ## Examples
+//!
+//! ```
+//! # #![feature(associated_consts, proc_macro)]
+//! # #[macro_use]
+//! # extern crate serde_derive;
+//! # extern crate futures;
+//! # extern crate webapp_demo;
+//! # use futures::finished;
+//! # use webapp_demo::host::*;
+//! #[derive(Deserialize)]
+//! struct Search {
+//!     page: Option<u32>,
+//!     #[serde(default)]
+//!     tag: Vec<String>,
+//! }
+//!
+//! struct SearchHandler;
+//!
+//! impl Route for SearchHandler {
+//!     const ROUTE: &'static str = "/search";
+//! }
+//!
+//! impl Get for SearchHandler {
+//!     fn call(&self, _: Params, req: Request) -> HttpFuture {
+//!         let Query(search) = match Query::<Search>::from_request(&req) {
+//!             Ok(query) => query,
+//!             Err(e) => return box finished(e.into()),
+//!         };
+//!
+//!         // Search for the tags
+//! # unimplemented!()
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+
+use std::mem;
+use std::vec;
+use serde::de::{self, Deserialize, Deserializer, MapVisitor, SeqVisitor, Visitor};
+use serde::de::value::Error as ValueError;
+use url::form_urlencoded;
+use errors::*;
+use super::Request;
+
+/// A value that's read from a query string.
+pub struct Query<T>(pub T);
+
+impl<T> Query<T>
+    where T: Deserialize
+{
+    /// Deserialise the query string of a request.
+    ///
+    /// A request without a query string is treated like an empty one.
+    pub fn from_request(req: &Request) -> Result<Self> {
+        Self::from_query(req.query().unwrap_or(""))
+    }
+
+    /// Deserialise a query string, without the leading `?`.
+    pub fn from_query(query: &str) -> Result<Self> {
+        T::deserialize(&mut QueryDeserializer(parse(query)))
+            .map(Query)
+            .map_err(|e| ErrorKind::InvalidQuery(e.to_string()).into())
+    }
+}
+
+/// Decode the pairs in a query string, grouping the values for each key.
+///
+/// Keys are kept in the order they first appear in.
+fn parse(query: &str) -> Vec<(String, Vec<String>)> {
+    let mut pairs: Vec<(String, Vec<String>)> = Vec::new();
+
+    for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+        match pairs.iter().position(|&(ref k, _)| *k == key) {
+            Some(i) => pairs[i].1.push(value.into_owned()),
+            None => pairs.push((key.into_owned(), vec![value.into_owned()])),
+        }
+    }
+
+    pairs
+}
+
+/// Deserialises a whole query string as a map of keys to values.
+struct QueryDeserializer(Vec<(String, Vec<String>)>);
+
+impl Deserializer for QueryDeserializer {
+    type Error = ValueError;
+
+    fn deserialize<V>(&mut self, mut visitor: V) -> ::std::result::Result<V::Value, Self::Error>
+        where V: Visitor
+    {
+        let pairs = mem::replace(&mut self.0, Vec::new());
+
+        visitor.visit_map(Pairs {
+            iter: pairs.into_iter(),
+            value: None,
+        })
+    }
+
+    forward_to_deserialize! {
+        bool usize u8 u16 u32 u64 isize i8 i16 i32 i64 f32 f64 char str string
+        unit option seq seq_fixed_size bytes map unit_struct newtype_struct
+        tuple_struct struct struct_field tuple enum ignored_any
+    }
+}
+
+/// Visits each key in a query string, along with its values.
+struct Pairs {
+    iter: vec::IntoIter<(String, Vec<String>)>,
+    value: Option<(String, Vec<String>)>,
+}
+
+impl MapVisitor for Pairs {
+    type Error = ValueError;
+
+    fn visit_key<K>(&mut self) -> ::std::result::Result<Option<K>, Self::Error>
+        where K: Deserialize
+    {
+        match self.iter.next() {
+            Some((key, values)) => {
+                let k = K::deserialize(&mut ValueDeserializer(key.clone()))?;
+                self.value = Some((key, values));
+
+                Ok(Some(k))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn visit_value<V>(&mut self) -> ::std::result::Result<V, Self::Error>
+        where V: Deserialize
+    {
+        match self.value.take() {
+            Some((key, values)) => {
+                V::deserialize(&mut ValuesDeserializer(values))
+                    .map_err(|e| de::Error::custom(format!("invalid value for '{}': {}", key, e)))
+            }
+            None => Err(de::Error::custom("a value was visited before its key")),
+        }
+    }
+
+    fn end(&mut self) -> ::std::result::Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+/// Deserialises all the values for a single key.
+///
+/// Sequences get every value, anything else gets the last one.
+struct ValuesDeserializer(Vec<String>);
+
+impl ValuesDeserializer {
+    fn last(&self) -> ValueDeserializer {
+        ValueDeserializer(self.0.last().cloned().unwrap_or_default())
+    }
+}
+
+macro_rules! forward_to_last {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V>(&mut self, visitor: V) -> ::std::result::Result<V::Value, Self::Error>
+                where V: Visitor
+            {
+                self.last().$method(visitor)
+            }
+        )*
+    }
+}
+
+impl Deserializer for ValuesDeserializer {
+    type Error = ValueError;
+
+    fn deserialize<V>(&mut self, visitor: V) -> ::std::result::Result<V::Value, Self::Error>
+        where V: Visitor
+    {
+        self.last().deserialize(visitor)
+    }
+
+    fn deserialize_option<V>(&mut self, mut visitor: V) -> ::std::result::Result<V::Value, Self::Error>
+        where V: Visitor
+    {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_seq<V>(&mut self, mut visitor: V) -> ::std::result::Result<V::Value, Self::Error>
+        where V: Visitor
+    {
+        let values = mem::replace(&mut self.0, Vec::new());
+
+        visitor.visit_seq(Values(values.into_iter()))
+    }
+
+    fn deserialize_seq_fixed_size<V>(&mut self, _: usize, visitor: V) -> ::std::result::Result<V::Value, Self::Error>
+        where V: Visitor
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple<V>(&mut self, _: usize, visitor: V) -> ::std::result::Result<V::Value, Self::Error>
+        where V: Visitor
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    forward_to_last! {
+        deserialize_bool deserialize_usize deserialize_u8 deserialize_u16 deserialize_u32
+        deserialize_u64 deserialize_isize deserialize_i8 deserialize_i16 deserialize_i32
+        deserialize_i64 deserialize_f32 deserialize_f64
+    }
+
+    forward_to_deserialize! {
+        char str string unit bytes map unit_struct newtype_struct tuple_struct struct
+        struct_field enum ignored_any
+    }
+}
+
+/// Visits each of the values for a single key.
+struct Values(vec::IntoIter<String>);
+
+impl SeqVisitor for Values {
+    type Error = ValueError;
+
+    fn visit<T>(&mut self) -> ::std::result::Result<Option<T>, Self::Error>
+        where T: Deserialize
+    {
+        match self.0.next() {
+            Some(value) => T::deserialize(&mut ValueDeserializer(value)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn end(&mut self) -> ::std::result::Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+/// Deserialises a single value, parsing it if a number or `bool` is
+/// expected.
+struct ValueDeserializer(String);
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident: $ty:ty,)*) => {
+        $(
+            fn $method<V>(&mut self, mut visitor: V) -> ::std::result::Result<V::Value, Self::Error>
+                where V: Visitor
+            {
+                let value: $ty = self.0
+                    .parse()
+                    .map_err(|_| de::Error::custom(format!("'{}' isn't a valid {}", self.0, stringify!($ty))))?;
+
+                visitor.$visit(value)
+            }
+        )*
+    }
+}
+
+impl Deserializer for ValueDeserializer {
+    type Error = ValueError;
+
+    fn deserialize<V>(&mut self, mut visitor: V) -> ::std::result::Result<V::Value, Self::Error>
+        where V: Visitor
+    {
+        visitor.visit_string(mem::replace(&mut self.0, String::new()))
+    }
+
+    fn deserialize_option<V>(&mut self, mut visitor: V) -> ::std::result::Result<V::Value, Self::Error>
+        where V: Visitor
+    {
+        visitor.visit_some(self)
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool: bool,
+        deserialize_usize => visit_usize: usize,
+        deserialize_u8 => visit_u8: u8,
+        deserialize_u16 => visit_u16: u16,
+        deserialize_u32 => visit_u32: u32,
+        deserialize_u64 => visit_u64: u64,
+        deserialize_isize => visit_isize: isize,
+        deserialize_i8 => visit_i8: i8,
+        deserialize_i16 => visit_i16: i16,
+        deserialize_i32 => visit_i32: i32,
+        deserialize_i64 => visit_i64: i64,
+        deserialize_f32 => visit_f32: f32,
+        deserialize_f64 => visit_f64: f64,
+    }
+
+    forward_to_deserialize! {
+        char str string unit seq seq_fixed_size bytes map unit_struct newtype_struct
+        tuple_struct struct struct_field tuple enum ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use errors::ErrorKind;
+    use super::*;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Search {
+        page: Option<u32>,
+        #[serde(default)]
+        tag: Vec<String>,
+    }
+
+    #[test]
+    fn repeated_keys_are_collected() {
+        let Query(search) = Query::<Search>::from_query("tag=a&page=2&tag=b").unwrap();
+
+        assert_eq!(Search {
+                       page: Some(2),
+                       tag: vec!["a".to_owned(), "b".to_owned()],
+                   },
+                   search);
+    }
+
+    #[test]
+    fn missing_fields_use_defaults() {
+        let Query(search) = Query::<Search>::from_query("").unwrap();
+
+        assert_eq!(Search {
+                       page: None,
+                       tag: vec![],
+                   },
+                   search);
+    }
+
+    #[test]
+    fn values_are_percent_decoded() {
+        let Query(search) = Query::<Search>::from_query("tag=a%20b&tag=c+d").unwrap();
+
+        assert_eq!(vec!["a b".to_owned(), "c d".to_owned()], search.tag);
+    }
+
+    #[test]
+    fn invalid_number_is_err() {
+        match Query::<Search>::from_query("page=two") {
+            Ok(_) => panic!("expected an error"),
+            Err(e) => {
+                match e.0 {
+                    ErrorKind::InvalidQuery(_) => (),
+                    kind => panic!("unexpected error: {:?}", kind),
+                }
+            }
+        }
+    }
+}
@@ -1,12 +1,162 @@
+use std::any::Any;
+use std::borrow::Cow;
+use std::boxed::FnBox;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use futures::{finished, Future};
-use hyper::{self, Get as GetMethod, Post as PostMethod};
+use hyper::{self, Body, Method, StatusCode};
+use hyper::header::{Allow, ContentLength, ContentType, Location};
 use hyper::server::{Service, Request, Response};
 use route_recognizer::Router as Recognizer;
 use errors::*;
-use super::{HttpFuture, Get, Post, Route};
+use super::{HttpFuture, Params, Get, Post, Put, Delete, Patch, Head, Options, Route};
+use super::metrics;
+use super::params::{self, with_params, Handled};
+use super::state::States;
+use super::openapi::{Document, Operation, OPENAPI_ROUTE};
+use super::urls::Urls;
+use super::path::{canonical, PathPolicy};
+use super::fallback::{Fallback, Fallbacks};
+use super::timeout::Timeout;
+use super::limit::ConcurrencyLimit;
+use super::panic::catch_panics;
+use super::middleware::{Chain, Middleware, Next};
 
-type HttpRouter<T> = Recognizer<Box<T>>;
+/// A handler for any method.
+///
+/// The method traits can't be stored together as trait objects, so each
+/// handler is wrapped up in a closure that calls the right trait method.
+/// The closure is reference counted so it can be moved into the end of
+/// a middleware chain.
+type HandlerFn = Arc<Fn(Params, Request) -> Handled + Send + Sync>;
+
+/// The end of a middleware chain, which will produce a response.
+type Respond = Box<FnBox(Request) -> HttpFuture>;
+
+/// A route pattern along with the handlers for each of its methods.
+#[derive(Clone)]
+struct Endpoint {
+    route: String,
+    handlers: HashMap<Method, HandlerFn>,
+    operations: HashMap<Method, Operation>,
+    middleware: Chain,
+}
+
+impl Endpoint {
+    fn new(route: String) -> Self {
+        Endpoint {
+            route: route,
+            handlers: HashMap::new(),
+            operations: HashMap::new(),
+            middleware: Arc::new(Vec::new()),
+        }
+    }
+
+    /// Call a handler for this endpoint through its middleware.
+    ///
+    /// If the handler can't be called then the request is given to the
+    /// fallbacks instead.
+    fn respond(&self, handler: HandlerFn, params: Params, fallbacks: Fallbacks) -> Respond {
+        let middleware = self.middleware.clone();
+
+        box move |req| {
+            let call: Respond = box move |req| {
+                match handler(params, req) {
+                    Ok(response) => response,
+                    Err((e, req)) => fallbacks.respond(e, req),
+                }
+            };
+
+            Next::new(middleware, call).call(req)
+        }
+    }
+
+    /// Decide how to handle a request for this route with the given method.
+    fn dispatch(&self, method: &Method) -> Dispatch {
+        if let Some(handler) = self.handlers.get(method) {
+            return Dispatch::Handler(handler);
+        }
+
+        match (method, self.handlers.get(&Method::Get)) {
+            (&Method::Head, Some(handler)) => Dispatch::HeadFromGet(handler),
+            (&Method::Options, _) => Dispatch::Options(self.methods()),
+            _ => Dispatch::NotAllowed(self.methods()),
+        }
+    }
+
+    /// Get the methods that can be used with this route.
+    ///
+    /// `HEAD` is allowed wherever `GET` is, and `OPTIONS` is always
+    /// allowed.
+    /// The methods are returned in a consistent order.
+    fn methods(&self) -> Vec<Method> {
+        let mut methods: Vec<Method> = self.handlers.keys().cloned().collect();
+
+        if self.handlers.contains_key(&Method::Get) && !self.handlers.contains_key(&Method::Head) {
+            methods.push(Method::Head);
+        }
+        if !self.handlers.contains_key(&Method::Options) {
+            methods.push(Method::Options);
+        }
+
+        methods.sort_by_key(method_order);
+
+        methods
+    }
+}
+
+/// How a request for an endpoint is handled.
+enum Dispatch<'a> {
+    /// Call the handler for the request's method.
+    Handler(&'a HandlerFn),
+    /// Call the `GET` handler and strip the body from its response.
+    HeadFromGet(&'a HandlerFn),
+    /// Answer an `OPTIONS` request with the allowed methods.
+    Options(Vec<Method>),
+    /// Respond with a `405 Method Not Allowed`.
+    NotAllowed(Vec<Method>),
+}
+
+/// The order methods are listed in, like in an `Allow` header.
+fn method_order(method: &Method) -> (usize, String) {
+    let order = match *method {
+        Method::Get => 0,
+        Method::Head => 1,
+        Method::Post => 2,
+        Method::Put => 3,
+        Method::Delete => 4,
+        Method::Patch => 5,
+        Method::Options => 6,
+        _ => 7,
+    };
+
+    (order, method.to_string())
+}
+
+/// A single table of routes for all methods.
+///
+/// Each route pattern is only added to the recognizer once, so a request
+/// is only matched once regardless of its method.
+struct RouteTable {
+    recognizer: Recognizer<usize>,
+    endpoints: Vec<Endpoint>,
+    state: States,
+    urls: Urls,
+    path_policy: PathPolicy,
+    fallbacks: Fallbacks,
+    /// Whether the router serves its own OpenAPI document.
+    openapi: bool,
+}
+
+/// A description of a route in a `Router`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RouteInfo {
+    /// The url pattern for the route.
+    pub route: String,
+    /// The methods that can be used with the route.
+    pub methods: Vec<Method>,
+}
 
 /// A `hyper` service that routes requests to child handlers.
 ///
@@ -14,60 +164,573 @@ type HttpRouter<T> = Recognizer<Box<T>>;
 /// increment a single [`Arc`]() pointer.
 #[derive(Clone)]
 pub struct Router {
-    routers: Arc<Box<Routers>>,
-}
-
-struct Routers {
-    get_router: HttpRouter<Get>,
-    post_router: HttpRouter<Post>,
+    routes: Arc<Box<RouteTable>>,
+    middleware: Chain,
 }
 
 pub struct RouterBuilder {
-    get_router: HttpRouter<Get>,
-    post_router: HttpRouter<Post>,
+    endpoints: Vec<Endpoint>,
+    middleware: Vec<Arc<Middleware>>,
+    state: States,
+    duplicates: Vec<String>,
+    mounted: HashSet<String>,
+    openapi: Option<Document>,
+    names: HashMap<String, String>,
+    path_policy: PathPolicy,
+    fallbacks: Fallbacks,
+    error: Option<Error>,
 }
 
 impl RouterBuilder {
     /// Create a new router builder.
     pub fn new() -> Self {
         RouterBuilder {
-            get_router: HttpRouter::new(),
-            post_router: HttpRouter::new(),
+            endpoints: Vec::new(),
+            middleware: Vec::new(),
+            state: States::default(),
+            duplicates: Vec::new(),
+            mounted: HashSet::new(),
+            openapi: None,
+            names: HashMap::new(),
+            path_policy: PathPolicy::default(),
+            fallbacks: Fallbacks::default(),
+            error: None,
         }
     }
 
-    /// Add a new handler for a `GET` request.
-    pub fn get<H>(mut self, handler: H) -> Self
-        where H: Get + Route + 'static
+    /// Add state that's shared by all handlers.
+    ///
+    /// Handlers can borrow the state with `Params::state`, or use the
+    /// `State` extractor as their parameters.
+    /// There can only be one value of each type, so adding another value
+    /// of the same type replaces it.
+    pub fn with_state<S>(mut self, state: S) -> Self
+        where S: Any + Send + Sync
     {
-        self.get_router.add(H::ROUTE, Box::new(handler));
+        self.state.insert(state);
 
         self
     }
 
-    /// Add a new handler for a `POST` request.
-    pub fn post<H>(mut self, handler: H) -> Self
-        where H: Post + Route + 'static
+    /// Add a middleware that runs for every request.
+    pub fn middleware<M>(mut self, middleware: M) -> Self
+        where M: Middleware + 'static
+    {
+        self.middleware.push(Arc::new(middleware));
+
+        self
+    }
+
+    /// Fail requests that take longer than the given duration with a
+    /// `503 Service Unavailable`.
+    ///
+    /// Building the router fails if the duration is longer than
+    /// `max_timeout`.
+    pub fn timeout(self, duration: Duration) -> Self {
+        match Timeout::new(duration) {
+            Ok(timeout) => self.middleware(timeout),
+            Err(e) => self.fail(e),
+        }
+    }
+
+    /// Fail requests for a route pattern that take longer than the given
+    /// duration with a `503 Service Unavailable`.
+    ///
+    /// Building the router fails if the duration is longer than
+    /// `max_timeout`.
+    pub fn route_timeout(self, route: &str, duration: Duration) -> Self {
+        match Timeout::new(duration) {
+            Ok(timeout) => self.route_middleware(route, timeout),
+            Err(e) => self.fail(e),
+        }
+    }
+
+    /// Allow at most `max_in_flight` requests to be handled at once, with
+    /// `max_queued` more waiting for their turn.
+    ///
+    /// Requests beyond that are shed with a `503 Service Unavailable`.
+    /// Building the router fails if `max_in_flight` is zero.
+    pub fn concurrency_limit(self, max_in_flight: usize, max_queued: usize) -> Self {
+        match ConcurrencyLimit::new(max_in_flight, max_queued) {
+            Ok(limit) => self.middleware(limit),
+            Err(e) => self.fail(e),
+        }
+    }
+
+    /// Allow at most `max_in_flight` requests for a route pattern to be
+    /// handled at once, with `max_queued` more waiting for their turn.
+    ///
+    /// The limit's metrics are labelled with the route pattern.
+    /// Building the router fails if `max_in_flight` is zero.
+    pub fn route_concurrency_limit(self, route: &str, max_in_flight: usize, max_queued: usize) -> Self {
+        match ConcurrencyLimit::new(max_in_flight, max_queued) {
+            Ok(limit) => self.route_middleware(route, limit.name(route)),
+            Err(e) => self.fail(e),
+        }
+    }
+
+    /// Add a middleware that runs for requests matching a route pattern.
+    ///
+    /// The route should be the same pattern given by a handler's
+    /// `Route::ROUTE`, and the middleware runs for any of its methods.
+    /// Building the router fails if there's no handler for the route, so
+    /// a mistyped pattern is caught when the app starts.
+    pub fn route_middleware<M>(mut self, route: &str, middleware: M) -> Self
+        where M: Middleware + 'static
+    {
+        let index = self.endpoint(route);
+
+        Arc::make_mut(&mut self.endpoints[index].middleware).push(Arc::new(middleware));
+
+        self
+    }
+
+    /// Mount a router under a path prefix.
+    ///
+    /// Every route in the child router is added to this one with the
+    /// prefix in front of it, so a child route of `/person/:id` mounted
+    /// at `/api/v1` becomes `/api/v1/person/:id`.
+    ///
+    /// The child's middleware only runs for its own routes, after any
+    /// middleware for this router.
+    /// Requests under the prefix that don't match one of the child's
+    /// routes are handled by this router, so they don't go through the
+    /// child's middleware.
+    ///
+    /// State from the child is added to this router, unless this router
+    /// already has state of the same type.
+    /// The child's fallback handlers aren't used, errors for its routes are
+    /// handled by the fallbacks for this router.
+    /// If the child serves an OpenAPI document it isn't mounted, because
+    /// its paths don't include the prefix; call `openapi` on this router
+    /// to describe the child's routes instead.
+    ///
+    /// Middleware belongs to a route rather than a single handler, so a
+    /// mounted route can't share its pattern with any other route.
+    /// Building the router fails if a child route lands on a route this
+    /// router already has, or if a handler is added to a mounted route
+    /// afterwards.
+    pub fn mount(mut self, prefix: &str, router: Router) -> Self {
+        let prefix = prefix.trim_right_matches('/');
+
+        for child in &router.routes.endpoints {
+            // The child's document doesn't know about the prefix, so its
+            // routes are described by this router's document instead
+            if router.routes.openapi && child.route == OPENAPI_ROUTE {
+                continue;
+            }
+
+            let route = prefixed(prefix, &child.route);
+
+            if self.endpoints.iter().any(|endpoint| endpoint.route == route) {
+                self.duplicates.push(format!("{} is mounted over an existing route", route));
+                continue;
+            }
+
+            for (method, handler) in &child.handlers {
+                let operation = child.operations.get(method).cloned().unwrap_or_default();
+
+                self.insert(method.clone(), &route, operation, handler.clone());
+            }
+
+            let index = self.endpoint(&route);
+            let endpoint = &mut self.endpoints[index];
+
+            // The child's router middleware runs before its route middleware
+            let middleware = Arc::make_mut(&mut endpoint.middleware);
+            middleware.extend(router.middleware.iter().cloned());
+            middleware.extend(child.middleware.iter().cloned());
+
+            self.mounted.insert(route);
+        }
+
+        for (name, route) in router.routes.urls.routes() {
+            let route = prefixed(prefix, route);
+
+            self = self.name(name, &route);
+        }
+
+        self.state.merge(&router.routes.state);
+
+        self
+    }
+
+    /// Set what to do with paths that aren't in their canonical form.
+    ///
+    /// The default is `PathPolicy::MatchBoth`.
+    pub fn path_policy(mut self, policy: PathPolicy) -> Self {
+        self.path_policy = policy;
+
+        self
+    }
+
+    /// Handle requests for paths that don't match any route.
+    ///
+    /// By default these get a `404 Not Found` with a problem document.
+    pub fn not_found<F>(mut self, fallback: F) -> Self
+        where F: Fallback + 'static
     {
-        self.post_router.add(H::ROUTE, Box::new(handler));
+        self.fallbacks.not_found = Some(Arc::new(fallback));
+
+        self
+    }
+
+    /// Handle requests for paths that match a route, but not for the
+    /// request's method.
+    ///
+    /// By default these get a `405 Method Not Allowed` with a problem
+    /// document.
+    /// The `Allow` header is added to the response if the fallback doesn't
+    /// set it.
+    pub fn method_not_allowed<F>(mut self, fallback: F) -> Self
+        where F: Fallback + 'static
+    {
+        self.fallbacks.method_not_allowed = Some(Arc::new(fallback));
+
+        self
+    }
+
+    /// Handle any other errors the router runs into, like invalid url
+    /// parameters.
+    ///
+    /// By default these get a problem document with the status code for
+    /// the error.
+    pub fn on_error<F>(mut self, fallback: F) -> Self
+        where F: Fallback + 'static
+    {
+        self.fallbacks.on_error = Some(Arc::new(fallback));
+
+        self
+    }
+
+    /// Give a route pattern a name, so paths for it can be built with
+    /// `url_for`.
+    ///
+    /// Each name can only be given to one route, and building the router
+    /// fails if the route has no handlers.
+    pub fn name(mut self, name: &str, route: &str) -> Self {
+        if let Some(existing) = self.names.insert(name.to_owned(), route.to_owned()) {
+            if existing != route {
+                self.duplicates.push(format!("the name '{}' is given to both {} and {}", name, existing, route));
+            }
+        }
+
+        self
+    }
+
+    /// Add a handler for a method to the endpoint for a route.
+    fn add(mut self, method: Method, route: &str, operation: Operation, handler: HandlerFn) -> Self {
+        self.insert(method, route, operation, handler);
+
+        self
+    }
+
+    /// Insert a handler, keeping track of any that were already added for
+    /// the same route and method.
+    fn insert(&mut self, method: Method, route: &str, operation: Operation, handler: HandlerFn) {
+        if self.mounted.contains(route) {
+            self.duplicates.push(format!("{} {} can't be added to a mounted route", method, route));
+            return;
+        }
+
+        let index = self.endpoint(route);
+
+        self.endpoints[index].operations.insert(method.clone(), operation);
+
+        if self.endpoints[index].handlers.insert(method.clone(), handler).is_some() {
+            self.duplicates.push(format!("{} {} has more than one handler", method, route));
+        }
+    }
+
+    /// Keep an error to return from `build`.
+    ///
+    /// Only the first error is kept.
+    fn fail(mut self, err: Error) -> Self {
+        if self.error.is_none() {
+            self.error = Some(err);
+        }
 
         self
     }
 
+    /// Get the index of the endpoint for a route, adding one if needed.
+    fn endpoint(&mut self, route: &str) -> usize {
+        match self.endpoints.iter().position(|endpoint| endpoint.route == route) {
+            Some(index) => index,
+            None => {
+                self.endpoints.push(Endpoint::new(route.to_owned()));
+                self.endpoints.len() - 1
+            }
+        }
+    }
+
     /// Build a `Router`.
     ///
     /// This function consumes the builder and returns a new
     /// immutable router with the given handlers.
-    pub fn build(self) -> Router {
-        Router {
-            routers: Arc::new(Box::new(Routers {
-                get_router: self.get_router,
-                post_router: self.post_router,
+    ///
+    /// Building fails with an `ErrorKind::RouteConflict` listing every
+    /// conflict if the same route and method have more than one handler,
+    /// or if a request could match more than one route for a method, like
+    /// `/person/:id` and `/person/new`.
+    /// It also fails if middleware or a name was added for a route that has
+    /// no handlers.
+    /// If a timeout or concurrency limit given to the builder was invalid
+    /// then that error is returned instead.
+    /// That way mistakes in routing are caught when the app starts.
+    pub fn build(mut self) -> Result<Router> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+
+        let openapi = self.openapi.is_some();
+        if let Some(openapi) = self.openapi.take() {
+            self.add_openapi(openapi);
+        }
+
+        let mut conflicts = self.duplicates.clone();
+        conflicts.extend(self.overlaps());
+        conflicts.extend(self.unhandled());
+        conflicts.extend(self.unknown_names());
+
+        if !conflicts.is_empty() {
+            bail!(ErrorKind::RouteConflict(conflicts));
+        }
+
+        let urls = Urls::new(self.names);
+        self.state.insert(urls.clone());
+
+        let mut recognizer = Recognizer::new();
+
+        for (index, endpoint) in self.endpoints.iter().enumerate() {
+            recognizer.add(&endpoint.route, index);
+        }
+
+        Ok(Router {
+            routes: Arc::new(Box::new(RouteTable {
+                recognizer: recognizer,
+                endpoints: self.endpoints,
+                state: self.state,
+                urls: urls,
+                path_policy: self.path_policy,
+                fallbacks: self.fallbacks,
+                openapi: openapi,
             })),
+            middleware: Arc::new(self.middleware),
+        })
+    }
+
+    /// Serve an OpenAPI document describing the router at
+    /// `GET /openapi.json`.
+    ///
+    /// The document includes every route added to the router, including
+    /// ones added after calling this method.
+    pub fn openapi(mut self, title: &str, version: &str) -> Self {
+        self.openapi = Some(Document::new(title, version));
+
+        self
+    }
+
+    /// Fill in the OpenAPI document and add a handler to serve it.
+    fn add_openapi(&mut self, mut openapi: Document) {
+        for endpoint in &self.endpoints {
+            let mut methods: Vec<&Method> = endpoint.handlers.keys().collect();
+            methods.sort_by_key(|method| method_order(method));
+
+            for method in methods {
+                let operation = endpoint.operations.get(method).cloned().unwrap_or_default();
+
+                openapi.add(&endpoint.route, method, &operation);
+            }
+        }
+
+        let body = Arc::new(openapi.to_json());
+
+        let operation = Operation::new().summary("Get an OpenAPI document describing the api");
+        let handler: HandlerFn = Arc::new(move |_, _| -> Handled {
+            let response = Response::new()
+                .header(ContentType::json())
+                .header(ContentLength(body.len() as u64))
+                .body((*body).clone());
+
+            Ok(box finished(response))
+        });
+
+        self.insert(Method::Get, OPENAPI_ROUTE, operation, handler);
+    }
+
+    /// Find routes that were given middleware but no handlers.
+    ///
+    /// Without a handler, requests for these routes would get a `405`
+    /// instead of a `404`.
+    fn unhandled(&self) -> Vec<String> {
+        self.endpoints
+            .iter()
+            .filter(|endpoint| endpoint.handlers.is_empty())
+            .map(|endpoint| format!("{} has middleware but no handlers", endpoint.route))
+            .collect()
+    }
+
+    /// Find names given to routes that have no handlers.
+    ///
+    /// Without a handler, `url_for` would build links that `404`.
+    fn unknown_names(&self) -> Vec<String> {
+        let mut names: Vec<(&String, &String)> = self.names
+            .iter()
+            .filter(|&(_, route)| {
+                !self.endpoints.iter().any(|endpoint| endpoint.route == *route && !endpoint.handlers.is_empty())
+            })
+            .collect();
+        names.sort();
+
+        names.into_iter()
+            .map(|(name, route)| format!("the name '{}' is given to {}, which has no handlers", name, route))
+            .collect()
+    }
+
+    /// Find pairs of routes that a request could match for the same method.
+    fn overlaps(&self) -> Vec<String> {
+        let mut conflicts = Vec::new();
+
+        for (i, a) in self.endpoints.iter().enumerate() {
+            for b in &self.endpoints[i + 1..] {
+                if !overlaps(&a.route, &b.route) {
+                    continue;
+                }
+
+                let mut methods: Vec<&Method> = a.handlers
+                    .keys()
+                    .filter(|method| b.handlers.contains_key(*method))
+                    .collect();
+                methods.sort_by_key(|method| method_order(method));
+
+                for method in methods {
+                    if same_pattern(&a.route, &b.route) {
+                        conflicts.push(format!("{} {} and {} are the same route", method, a.route, b.route));
+                    } else {
+                        conflicts.push(format!("{} {} and {} match some of the same paths", method, a.route, b.route));
+                    }
+                }
+            }
+        }
+
+        conflicts
+    }
+}
+
+/// Add builder methods for handlers of each method trait.
+///
+/// Each method trait gets a method that adds a handler at its
+/// `Route::ROUTE`, and an `_at` method that adds it at any route.
+/// The trait's name is also the name of its `Method` variant.
+macro_rules! handler_methods {
+    ($($(#[$attr:meta])* fn $name:ident, $name_at:ident => $method:ident;)*) => {
+        impl RouterBuilder {
+            $(
+                $(#[$attr])*
+                pub fn $name<H>(self, handler: H) -> Self
+                    where H: $method + Route + 'static
+                {
+                    self.$name_at(H::ROUTE, handler)
+                }
+
+                /// Add a new handler at the given route instead of its
+                /// `Route::ROUTE`.
+                ///
+                /// This can be used to add the same type of handler at more
+                /// than one route, or to add a handler that doesn't implement
+                /// `Route`.
+                pub fn $name_at<H>(self, route: &str, handler: H) -> Self
+                    where H: $method + 'static
+                {
+                    self.add(Method::$method,
+                             route,
+                             $method::describe(&handler),
+                             Arc::new(move |params, req| {
+                                 with_params(params, req, |params, req| $method::call(&handler, params, req))
+                             }))
+                }
+            )*
         }
     }
 }
 
+handler_methods! {
+    /// Add a new handler for a `GET` request.
+    ///
+    /// The handler will also be used for `HEAD` requests to the same
+    /// route, unless a `Head` handler is added for it.
+    fn get, get_at => Get;
+    /// Add a new handler for a `POST` request.
+    fn post, post_at => Post;
+    /// Add a new handler for a `PUT` request.
+    fn put, put_at => Put;
+    /// Add a new handler for a `DELETE` request.
+    fn delete, delete_at => Delete;
+    /// Add a new handler for a `PATCH` request.
+    fn patch, patch_at => Patch;
+    /// Add a new handler for a `HEAD` request.
+    fn head, head_at => Head;
+    /// Add a new handler for an `OPTIONS` request.
+    fn options, options_at => Options;
+}
+
+/// Add a prefix to a route pattern.
+fn prefixed(prefix: &str, route: &str) -> String {
+    match (prefix, route) {
+        ("", "/") => "/".to_owned(),
+        (prefix, "/") => prefix.to_owned(),
+        (prefix, route) => format!("{}{}", prefix, route),
+    }
+}
+
+fn segments(route: &str) -> Vec<&str> {
+    route.split('/').filter(|segment| !segment.is_empty()).collect()
+}
+
+fn is_param(segment: &str) -> bool {
+    segment.starts_with(':')
+}
+
+fn is_glob(segment: &str) -> bool {
+    segment.starts_with('*')
+}
+
+/// Whether there's any path that would match both route patterns.
+fn overlaps(a: &str, b: &str) -> bool {
+    let (a, b) = (segments(a), segments(b));
+    let mut a = a.iter();
+    let mut b = b.iter();
+
+    loop {
+        match (a.next(), b.next()) {
+            (None, None) => return true,
+            (Some(x), Some(y)) => {
+                if is_glob(x) || is_glob(y) {
+                    return true;
+                }
+
+                if !(is_param(x) || is_param(y) || x == y) {
+                    return false;
+                }
+            }
+            // A glob has to match at least one segment, so `/a/*rest`
+            // doesn't match `/a`
+            (Some(_), None) | (None, Some(_)) => return false,
+        }
+    }
+}
+
+/// Whether two route patterns only differ by the names of their parameters.
+fn same_pattern(a: &str, b: &str) -> bool {
+    let (a, b) = (segments(a), segments(b));
+
+    a.len() == b.len() &&
+    a.iter().zip(b.iter()).all(|(x, y)| {
+        (is_param(x) && is_param(y)) || (is_glob(x) && is_glob(y)) || x == y
+    })
+}
+
 impl Service for Router {
     type Request = Request;
     type Response = Response;
@@ -75,56 +738,155 @@ impl Service for Router {
     type Future = HttpFuture;
 
     fn call(&self, req: Request) -> Self::Future {
-        match *req.method() {
-            GetMethod => self.get(req),
-            PostMethod => self.post(req),
-            _ => box finished(ErrorKind::MethodNotSupported.into()),
-        }
+        let start = Instant::now();
+        let method = req.method().clone();
+
+        let (route, respond) = self.dispatch(&req);
+        let middleware = self.middleware.clone();
+
+        // A panicking handler shouldn't take down the other requests on
+        // this reactor
+        let response = catch_panics(move || Next::new(middleware, respond).call(req));
+
+        metrics::instrument(route, method, start, response)
     }
 }
 
 impl Router {
-    fn get(&self, req: Request) -> <Self as Service>::Future {
-        req.path()
-            .ok_or({
-                Error::from(ErrorKind::NoRouteSpecified)
+    /// Get a description of every route in the router.
+    pub fn routes(&self) -> Vec<RouteInfo> {
+        self.routes
+            .endpoints
+            .iter()
+            .map(|endpoint| {
+                RouteInfo {
+                    route: endpoint.route.clone(),
+                    methods: endpoint.methods(),
+                }
             })
-            .and_then(|path| {
-                self.routers
-                    .get_router
-                    .recognize(path)
-                    .map_err(|_| ErrorKind::NoRouteMatch(path.to_owned()).into())
-            })
-            .and_then(|route| {
-                let handler = route.handler;
-                let params = route.params;
+            .collect()
+    }
 
-                Ok(handler.call(params, req))
-            })
-            .unwrap_or_else(|e| box finished(Response::from(e)))
+    /// Build the path for a named route by filling in its parameters.
+    ///
+    /// Handlers can do the same with the `Urls` state.
+    pub fn url_for(&self, name: &str, params: &[(&str, &str)]) -> Result<String> {
+        self.routes.urls.url_for(name, params)
     }
 
-    fn post(&self, req: Request) -> <Self as Service>::Future {
-        req.path()
-            .ok_or({
-                Error::from(ErrorKind::NoRouteSpecified)
-            })
-            .and_then(|path| {
-                self.routers
-                    .post_router
-                    .recognize(path)
-                    .map_err(|_| ErrorKind::NoRouteMatch(path.to_owned()).into())
-            })
-            .and_then(|route| {
-                let handler = route.handler;
-                let params = route.params;
+    /// Work out how to respond to a request, returning its route pattern
+    /// and the end of its middleware chain.
+    ///
+    /// The path of the request is matched to an endpoint first, and then
+    /// the method is matched to one of its handlers.
+    /// If the path is unknown then we respond with a `404 Not Found`.
+    /// If the path is known but the method isn't then we respond with a
+    /// `405 Method Not Allowed`, unless it's a `HEAD` or `OPTIONS` request
+    /// we can answer on behalf of the endpoint.
+    fn dispatch(&self, req: &Request) -> (String, Respond) {
+        if let Some(redirect) = self.redirect(req) {
+            return redirect;
+        }
 
-                Ok(handler.call(params, req))
-            })
-            .unwrap_or_else(|e| box finished(Response::from(e)))
+        let (endpoint, params) = match self.recognize(req) {
+            Ok(route) => route,
+            Err(e) => return (metrics::UNMATCHED.to_owned(), self.respond_error(e)),
+        };
+
+        let respond: Respond = match endpoint.dispatch(req.method()) {
+            Dispatch::Handler(handler) => endpoint.respond(handler.clone(), params, self.routes.fallbacks.clone()),
+            Dispatch::HeadFromGet(handler) => {
+                let respond = endpoint.respond(handler.clone(), params, self.routes.fallbacks.clone());
+
+                box move |req| -> HttpFuture {
+                    box respond(req).map(|response| response.body(Body::empty()))
+                }
+            }
+            Dispatch::Options(allowed) => {
+                let response = Response::new()
+                    .header(Allow(allowed))
+                    .header(ContentLength(0));
+
+                respond_with(response)
+            }
+            Dispatch::NotAllowed(allowed) => self.respond_error(ErrorKind::MethodNotSupported(allowed).into()),
+        };
+
+        (endpoint.route.clone(), respond)
+    }
+
+    /// Respond to a request with an error, using the fallback handlers.
+    fn respond_error(&self, err: Error) -> Respond {
+        let fallbacks = self.routes.fallbacks.clone();
+
+        box move |req| fallbacks.respond(err, req)
+    }
+
+    /// Redirect a request to the canonical form of its path.
+    ///
+    /// This only happens if the path policy is `Redirect`, the path isn't
+    /// canonical already, and the canonical path matches a route.
+    fn redirect(&self, req: &Request) -> Option<(String, Respond)> {
+        if self.routes.path_policy != PathPolicy::Redirect {
+            return None;
+        }
+
+        let path = match req.path() {
+            Some(path) => path,
+            None => return None,
+        };
+
+        let canonical = match canonical(path) {
+            Cow::Owned(canonical) => canonical,
+            Cow::Borrowed(_) => return None,
+        };
+
+        let endpoint = match self.routes.recognizer.recognize(&canonical) {
+            Ok(route) => &self.routes.endpoints[*route.handler],
+            Err(_) => return None,
+        };
+
+        let location = match req.query() {
+            Some(query) => format!("{}?{}", canonical, query),
+            None => canonical,
+        };
+
+        let response = Response::new()
+            .status(StatusCode::PermanentRedirect)
+            .header(Location(location))
+            .header(ContentLength(0));
+
+        Some((endpoint.route.clone(), respond_with(response)))
+    }
+
+    /// Match the path of a request to an endpoint.
+    ///
+    /// If the path policy is `MatchBoth` then the canonical form of the
+    /// path is matched instead.
+    fn recognize(&self, req: &Request) -> Result<(&Endpoint, Params)> {
+        let path = req.path().ok_or(Error::from(ErrorKind::NoRouteSpecified))?;
+
+        let path = match self.routes.path_policy {
+            PathPolicy::MatchBoth => canonical(path),
+            PathPolicy::Strict | PathPolicy::Redirect => Cow::Borrowed(path),
+        };
+
+        let route = self.routes
+            .recognizer
+            .recognize(&path)
+            .map_err(|_| Error::from(ErrorKind::NoRouteMatch(path.clone().into_owned())))?;
+
+        let endpoint = &self.routes.endpoints[*route.handler];
+
+        Ok((endpoint, params::from_route(route.params, self.routes.state.clone())))
     }
 }
 
+/// Respond to a request with a response that's already known.
+fn respond_with(response: Response) -> Respond {
+    box move |_| -> HttpFuture { box finished(response) }
+}
+
 /// A conversion trait for handler futures.
 ///
 /// This is a convenience trait for taking any future with a `Response`
@@ -158,3 +920,295 @@ impl<F> IntoHttpFuture for F
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use hyper::Method;
+    use errors::ErrorKind;
+    use host::{HttpFuture, Get, Post, Params, Request, Next};
+    use host::params::Handled;
+    use super::*;
+
+    struct Handler;
+
+    impl Get for Handler {
+        fn call(&self, _: Params, _: Request) -> HttpFuture {
+            unimplemented!()
+        }
+    }
+
+    impl Post for Handler {
+        fn call(&self, _: Params, _: Request) -> HttpFuture {
+            unimplemented!()
+        }
+    }
+
+    fn conflicts(builder: RouterBuilder) -> Vec<String> {
+        match builder.build() {
+            Ok(_) => panic!("expected the router to fail to build"),
+            Err(e) => {
+                match e.0 {
+                    ErrorKind::RouteConflict(conflicts) => conflicts,
+                    kind => panic!("unexpected error: {:?}", kind),
+                }
+            }
+        }
+    }
+
+    fn endpoint(methods: &[Method]) -> Endpoint {
+        let mut endpoint = Endpoint::new("/a".to_owned());
+
+        for method in methods {
+            let handler: HandlerFn = Arc::new(|_, _| -> Handled { unimplemented!() });
+
+            endpoint.handlers.insert(method.clone(), handler);
+        }
+
+        endpoint
+    }
+
+    fn allowed(dispatch: Dispatch) -> Option<Vec<Method>> {
+        match dispatch {
+            Dispatch::NotAllowed(allowed) => Some(allowed),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn methods_are_ordered() {
+        let endpoint = endpoint(&[Method::Delete, Method::Post, Method::Get]);
+
+        assert_eq!(vec![Method::Get, Method::Head, Method::Post, Method::Delete, Method::Options],
+                   endpoint.methods());
+    }
+
+    #[test]
+    fn methods_without_get_dont_include_head() {
+        let endpoint = endpoint(&[Method::Put]);
+
+        assert_eq!(vec![Method::Put, Method::Options], endpoint.methods());
+    }
+
+    #[test]
+    fn methods_include_options_handler_once() {
+        let endpoint = endpoint(&[Method::Options, Method::Head]);
+
+        assert_eq!(vec![Method::Head, Method::Options], endpoint.methods());
+    }
+
+    #[test]
+    fn dispatch_to_handler_for_method() {
+        let endpoint = endpoint(&[Method::Get, Method::Post]);
+
+        match endpoint.dispatch(&Method::Post) {
+            Dispatch::Handler(_) => (),
+            _ => panic!("expected the POST handler"),
+        }
+    }
+
+    #[test]
+    fn dispatch_head_to_get() {
+        let endpoint = endpoint(&[Method::Get]);
+
+        match endpoint.dispatch(&Method::Head) {
+            Dispatch::HeadFromGet(_) => (),
+            _ => panic!("expected the GET handler"),
+        }
+    }
+
+    #[test]
+    fn dispatch_head_to_head_handler() {
+        let endpoint = endpoint(&[Method::Get, Method::Head]);
+
+        match endpoint.dispatch(&Method::Head) {
+            Dispatch::Handler(_) => (),
+            _ => panic!("expected the HEAD handler"),
+        }
+    }
+
+    #[test]
+    fn dispatch_options_lists_methods() {
+        let endpoint = endpoint(&[Method::Get]);
+
+        match endpoint.dispatch(&Method::Options) {
+            Dispatch::Options(allowed) => assert_eq!(vec![Method::Get, Method::Head, Method::Options], allowed),
+            _ => panic!("expected an automatic OPTIONS response"),
+        }
+    }
+
+    #[test]
+    fn dispatch_unsupported_method_is_not_allowed() {
+        let endpoint = endpoint(&[Method::Post]);
+
+        assert_eq!(Some(vec![Method::Post, Method::Options]), allowed(endpoint.dispatch(&Method::Get)));
+        assert_eq!(Some(vec![Method::Post, Method::Options]), allowed(endpoint.dispatch(&Method::Head)));
+    }
+
+    #[test]
+    fn param_overlaps_static_segment() {
+        assert!(overlaps("/a/:id", "/a/new"));
+        assert!(overlaps("/a/new", "/a/:id"));
+    }
+
+    #[test]
+    fn different_static_segments_dont_overlap() {
+        assert!(!overlaps("/a/b", "/a/c"));
+        assert!(!overlaps("/a/:id", "/a/:id/b"));
+    }
+
+    #[test]
+    fn glob_overlaps_longer_routes() {
+        assert!(overlaps("/a/*g", "/a/b"));
+        assert!(overlaps("/a/*g", "/a/b/c"));
+        assert!(overlaps("/a/*g", "/a/:id"));
+    }
+
+    #[test]
+    fn glob_doesnt_overlap_parent_route() {
+        assert!(!overlaps("/a/*g", "/a"));
+        assert!(!overlaps("/a", "/a/*g"));
+    }
+
+    #[test]
+    fn routes_that_differ_by_param_name_are_the_same_pattern() {
+        assert!(same_pattern("/a/:id", "/a/:name"));
+        assert!(same_pattern("/a/*g", "/a/*rest"));
+        assert!(!same_pattern("/a/:id", "/a/new"));
+    }
+
+    #[test]
+    fn build_fails_for_duplicate_handlers() {
+        let builder = RouterBuilder::new()
+            .get_at("/a", Handler)
+            .get_at("/a", Handler);
+
+        assert_eq!(vec!["GET /a has more than one handler".to_owned()], conflicts(builder));
+    }
+
+    #[test]
+    fn build_fails_for_overlapping_routes() {
+        let builder = RouterBuilder::new()
+            .get_at("/a/:id", Handler)
+            .get_at("/a/new", Handler);
+
+        assert_eq!(vec!["GET /a/:id and /a/new match some of the same paths".to_owned()],
+                   conflicts(builder));
+    }
+
+    #[test]
+    fn build_fails_for_routes_that_differ_by_param_name() {
+        let builder = RouterBuilder::new()
+            .get_at("/a/:id", Handler)
+            .get_at("/a/:name", Handler);
+
+        assert_eq!(vec!["GET /a/:id and /a/:name are the same route".to_owned()],
+                   conflicts(builder));
+    }
+
+    #[test]
+    fn build_allows_overlapping_routes_for_different_methods() {
+        let router = RouterBuilder::new()
+            .get_at("/a/:id", Handler)
+            .post_at("/a/new", Handler)
+            .build();
+
+        assert!(router.is_ok());
+    }
+
+    #[test]
+    fn build_allows_glob_next_to_parent_route() {
+        let router = RouterBuilder::new()
+            .get_at("/a", Handler)
+            .get_at("/a/*rest", Handler)
+            .build();
+
+        assert!(router.is_ok());
+    }
+
+    #[test]
+    fn build_fails_for_mount_over_existing_route() {
+        let child = RouterBuilder::new()
+            .post_at("/x", Handler)
+            .build()
+            .unwrap();
+
+        let builder = RouterBuilder::new()
+            .get_at("/api/v1/x", Handler)
+            .mount("/api/v1", child);
+
+        assert_eq!(vec!["/api/v1/x is mounted over an existing route".to_owned()],
+                   conflicts(builder));
+    }
+
+    #[test]
+    fn build_fails_for_handler_added_to_mounted_route() {
+        let child = RouterBuilder::new()
+            .post_at("/x", Handler)
+            .build()
+            .unwrap();
+
+        let builder = RouterBuilder::new()
+            .mount("/api/v1", child)
+            .get_at("/api/v1/x", Handler);
+
+        assert_eq!(vec!["GET /api/v1/x can't be added to a mounted route".to_owned()],
+                   conflicts(builder));
+    }
+
+    #[test]
+    fn mount_adds_prefixed_routes() {
+        let child = RouterBuilder::new()
+            .post_at("/x", Handler)
+            .build()
+            .unwrap();
+
+        let router = RouterBuilder::new()
+            .get_at("/y", Handler)
+            .mount("/api/v1/", child)
+            .build()
+            .unwrap();
+
+        let routes: Vec<String> = router.routes().into_iter().map(|info| info.route).collect();
+
+        assert_eq!(vec!["/y".to_owned(), "/api/v1/x".to_owned()], routes);
+    }
+
+    #[test]
+    fn mount_skips_child_openapi_document() {
+        let child = RouterBuilder::new()
+            .post_at("/x", Handler)
+            .openapi("child", "1")
+            .build()
+            .unwrap();
+
+        let router = RouterBuilder::new()
+            .mount("/api/v1", child)
+            .build()
+            .unwrap();
+
+        let routes: Vec<String> = router.routes().into_iter().map(|info| info.route).collect();
+
+        assert_eq!(vec!["/api/v1/x".to_owned()], routes);
+    }
+
+    #[test]
+    fn build_fails_for_middleware_without_handlers() {
+        let builder = RouterBuilder::new()
+            .get_at("/a", Handler)
+            .route_middleware("/b", |req: Request, next: Next| -> HttpFuture { next.call(req) });
+
+        assert_eq!(vec!["/b has middleware but no handlers".to_owned()], conflicts(builder));
+    }
+
+    #[test]
+    fn build_fails_for_name_without_handlers() {
+        let builder = RouterBuilder::new()
+            .get_at("/person/:id", Handler)
+            .name("person", "/person/:id")
+            .name("people", "/people");
+
+        assert_eq!(vec!["the name 'people' is given to /people, which has no handlers".to_owned()],
+                   conflicts(builder));
+    }
+}
@@ -0,0 +1,166 @@
+//! # Graceful shutdown
+//!
+//! When the server is asked to stop we don't want to drop requests that
+//! are already being processed, including any work they've handed off to
+//! a background pool.
+//! The `Drain` service wraps another service and keeps count of the requests
+//! it has in flight.
+//! A request is in flight from when it's received until its response future
+//! completes or is dropped.
+//!
+//! Once draining has started, new requests are turned away with a
+//! `503 Service Unavailable` and `Connection: close`, so clients and load
+//! balancers know to go elsewhere, while existing requests are left to finish.
+//!
+//! ## Examples
+//!
+//! ```no_run
+//! # #![feature(associated_consts)]
+//! # extern crate webapp_demo;
+//! # extern crate tokio_core;
+//! # use std::time::Duration;
+//! # use tokio_core::reactor::Core;
+//! # use webapp_demo::host::*;
+//! # fn main() {
+//! # let mut core = Core::new().unwrap();
+//! # let handle = core.handle();
+//! let router = RouterBuilder::new().build().unwrap();
+//!
+//! let service = Drain::new(router);
+//! let drain = service.handle();
+//!
+//! // Serve requests using `service`, until we want to stop...
+//!
+//! drain.start();
+//! core.run(drain.drained(&handle)).unwrap();
+//! # }
+//! ```
+
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+use futures::{failed, finished, Future, Stream};
+use hyper;
+use hyper::header::Connection;
+use hyper::server::Service;
+use tokio_core::reactor::{Handle, Interval};
+use super::{HttpFuture, Request, Response, StatusCode};
+
+/// How often to check whether in-flight requests have finished.
+const DRAIN_POLL_MS: u64 = 50;
+
+/// A service that tracks in-flight requests so they can be drained.
+///
+/// Like the `Router`, this service is cheap to clone, and all clones
+/// share the same count of in-flight requests.
+#[derive(Clone)]
+pub struct Drain<S> {
+    service: S,
+    state: Arc<DrainState>,
+}
+
+/// A handle for draining the requests of a `Drain` service.
+#[derive(Clone)]
+pub struct DrainHandle {
+    state: Arc<DrainState>,
+}
+
+struct DrainState {
+    draining: AtomicBool,
+    in_flight: AtomicUsize,
+}
+
+/// Marks a single request as in flight until it's dropped.
+///
+/// Using a guard means a request stops being counted even if its future
+/// is dropped before it completes.
+struct InFlight(Arc<DrainState>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl<S> Drain<S> {
+    /// Wrap a service so its requests can be drained.
+    pub fn new(service: S) -> Self {
+        Drain {
+            service: service,
+            state: Arc::new(DrainState {
+                draining: AtomicBool::new(false),
+                in_flight: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Get a handle for draining this service.
+    pub fn handle(&self) -> DrainHandle {
+        DrainHandle { state: self.state.clone() }
+    }
+}
+
+impl<S> Service for Drain<S>
+    where S: Service<Request = Request, Response = Response, Error = hyper::Error>,
+          S::Future: 'static
+{
+    type Request = Request;
+    type Response = Response;
+    type Error = hyper::Error;
+    type Future = HttpFuture;
+
+    fn call(&self, req: Request) -> Self::Future {
+        if self.state.draining.load(Ordering::SeqCst) {
+            let response = Response::new()
+                .status(StatusCode::ServiceUnavailable)
+                .header(Connection::close());
+
+            return box finished(response);
+        }
+
+        self.state.in_flight.fetch_add(1, Ordering::SeqCst);
+        let in_flight = InFlight(self.state.clone());
+
+        box self.service.call(req).then(move |response| {
+            drop(in_flight);
+
+            response
+        })
+    }
+}
+
+impl DrainHandle {
+    /// Start draining.
+    ///
+    /// Any requests received after this point are rejected.
+    pub fn start(&self) {
+        self.state.draining.store(true, Ordering::SeqCst);
+    }
+
+    /// Whether or not draining has started.
+    pub fn is_draining(&self) -> bool {
+        self.state.draining.load(Ordering::SeqCst)
+    }
+
+    /// The number of requests currently in flight.
+    pub fn in_flight(&self) -> usize {
+        self.state.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Get a future that completes once there are no requests in flight.
+    ///
+    /// This future doesn't time out by itself, so it should be raced
+    /// against a deadline.
+    pub fn drained(&self, handle: &Handle) -> Box<Future<Item = (), Error = io::Error>> {
+        let state = self.state.clone();
+
+        let interval = match Interval::new(Duration::from_millis(DRAIN_POLL_MS), handle) {
+            Ok(interval) => interval,
+            Err(e) => return box failed(e),
+        };
+
+        box interval.take_while(move |_| Ok(state.in_flight.load(Ordering::SeqCst) > 0))
+            .for_each(|_| Ok(()))
+    }
+}
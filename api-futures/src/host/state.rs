@@ -0,0 +1,106 @@
+//! # Shared state
+//!
+//! Handlers often need access to things that are shared across the whole
+//! app, like database pools, configuration or caches.
+//! Rather than cloning these into every handler, they can be added to the
+//! router once with `RouterBuilder::with_state`, and then borrowed from the
+//! `Params` on each call.
+//!
+//! State is keyed by its type, so there can only be one value of each type.
+//! Wrapping values in a newtype is a simple way to add more than one of the
+//! same underlying type.
+//!
+//! Handlers can get the state by reference with `Params::state`, or declare
+//! it as their parameters with the `State` extractor.
+//!
+//! ## Examples
+//!
+//! ```
+//! # #![feature(associated_consts)]
+//! # extern crate webapp_demo;
+//! # use webapp_demo::host::*;
+//! struct Greeting(String);
+//!
+//! struct GreetingHandler;
+//!
+//! impl Route for GreetingHandler {
+//!     const ROUTE: &'static str = "/";
+//! }
+//!
+//! impl Get for GreetingHandler {
+//!     type Params = State<Greeting>;
+//!
+//!     fn call(&self, greeting: State<Greeting>, _: Request) -> HttpFuture {
+//!         let msg = &greeting.0;
+//!
+//!         // Respond with the greeting
+//! # unimplemented!()
+//!     }
+//! }
+//!
+//! # fn main() {
+//! let router = RouterBuilder::new()
+//!     .with_state(Greeting("Hello world".to_owned()))
+//!     .get(GreetingHandler)
+//!     .build()
+//!     .unwrap();
+//! # }
+//! ```
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::Arc;
+
+/// The state added to a router, keyed by type.
+///
+/// Each value is stored as an `Arc<S>`, so the `State` extractor can hold
+/// on to it without borrowing from the router.
+/// The `Arc<S>` is itself reference counted as an `Any`, so the map can be
+/// cloned without knowing the types in it.
+#[derive(Clone, Default)]
+pub struct States(Arc<HashMap<TypeId, Arc<Any + Send + Sync>>>);
+
+impl States {
+    /// Add a value, replacing any existing value of the same type.
+    pub fn insert<S>(&mut self, state: S)
+        where S: Any + Send + Sync
+    {
+        Arc::make_mut(&mut self.0).insert(TypeId::of::<S>(), Arc::new(Arc::new(state)));
+    }
+
+    /// Add the values from another map that don't have a value of the same
+    /// type in this one.
+    pub fn merge(&mut self, other: &States) {
+        let states = Arc::make_mut(&mut self.0);
+
+        for (id, state) in other.0.iter() {
+            states.entry(*id).or_insert_with(|| state.clone());
+        }
+    }
+
+    /// Get the value of a given type.
+    pub fn get<S>(&self) -> Option<&Arc<S>>
+        where S: Any + Send + Sync
+    {
+        self.0
+            .get(&TypeId::of::<S>())
+            .and_then(|state| (&**state as &Any).downcast_ref::<Arc<S>>())
+    }
+}
+
+/// Shared state of type `S`, as handler parameters.
+///
+/// Using `State<S>` as the `Params` for a handler means it's called with the
+/// value of type `S` that was added to the router.
+/// If there's no value of that type then the request fails with a
+/// `500 Internal Server Error`.
+pub struct State<S>(pub Arc<S>);
+
+impl<S> Deref for State<S> {
+    type Target = S;
+
+    fn deref(&self) -> &S {
+        &self.0
+    }
+}
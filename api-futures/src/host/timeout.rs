@@ -0,0 +1,160 @@
+//! # Timeouts
+//!
+//! A handler future that never completes would hold on to its connection
+//! forever.
+//! The `Timeout` middleware races the rest of the chain against a timer,
+//! and if the timer fires first then the handler future is dropped, which
+//! cancels it, and the request gets a `503 Service Unavailable`.
+//!
+//! A timeout can be added for the whole router with `RouterBuilder::timeout`,
+//! or for a single route with `RouterBuilder::route_timeout`.
+//! Router middleware runs before route middleware, so a route timeout can
+//! shorten the router timeout for that route, but not lengthen it.
+//!
+//! Timeouts can't be longer than `max_timeout`, because that's as far
+//! ahead as the shared timer can schedule.
+//! A timeout that's too long fails when the router is built, rather than
+//! quietly never firing.
+//!
+//! The shared timer can also run out of room when a lot of timeouts are
+//! registered at once.
+//! It's sized for `TIMER_CAPACITY` pending timeouts, and if it's still
+//! full then the request fails straight away with a
+//! `503 Service Unavailable` rather than running without a deadline.
+//!
+//! ## Examples
+//!
+//! ```
+//! # #![feature(associated_consts)]
+//! # extern crate webapp_demo;
+//! # use std::time::Duration;
+//! # use webapp_demo::host::*;
+//! # struct PersonHandler;
+//! # impl Route for PersonHandler {
+//! # const ROUTE: &'static str = "/person/:id";
+//! # }
+//! # impl Get for PersonHandler {
+//! # fn call(&self, _: Params, _: Request) -> HttpFuture {
+//! # unimplemented!()
+//! # }
+//! # }
+//! # fn main() {
+//! let router = RouterBuilder::new()
+//!     .get(PersonHandler)
+//!     .timeout(Duration::from_secs(30))
+//!     .route_timeout("/person/:id", Duration::from_secs(5))
+//!     .build()
+//!     .unwrap();
+//! # }
+//! ```
+
+use std::time::Duration;
+use std::io::{self, Write};
+use futures::{finished, Future};
+use tokio_timer::{self, Timer};
+use errors::*;
+use super::{HttpFuture, Request};
+use super::middleware::{Middleware, Next};
+
+/// The resolution of the shared timer.
+const TICK_MS: u64 = 100;
+
+/// The number of slots in the shared timer's wheel.
+///
+/// Together with `TICK_MS`, this decides the longest timeout the timer
+/// can schedule.
+const NUM_SLOTS: usize = 4096;
+
+/// The number of timeouts that can be pending on the shared timer.
+///
+/// Each request with a timeout holds one until it completes.
+pub const TIMER_CAPACITY: usize = 65_536;
+
+/// The number of timeouts that can be waiting to be registered with the
+/// shared timer's thread.
+const TIMER_CHANNEL_CAPACITY: usize = 8192;
+
+lazy_static! {
+    // Each timer runs its own thread, so they're shared by all timeouts
+    static ref TIMER: Timer = tokio_timer::wheel()
+        .tick_duration(Duration::from_millis(TICK_MS))
+        .num_slots(NUM_SLOTS)
+        .max_timeout(max_timeout())
+        .capacity(TIMER_CAPACITY)
+        .channel_capacity(TIMER_CHANNEL_CAPACITY)
+        .build();
+}
+
+/// The longest timeout that can be used.
+pub fn max_timeout() -> Duration {
+    Duration::from_millis(TICK_MS * NUM_SLOTS as u64)
+}
+
+/// A middleware that fails requests that take too long.
+#[derive(Debug, Clone, Copy)]
+pub struct Timeout {
+    duration: Duration,
+}
+
+impl Timeout {
+    /// Create a timeout with the given duration.
+    ///
+    /// This fails if the duration is longer than `max_timeout`.
+    pub fn new(duration: Duration) -> Result<Self> {
+        let max = max_timeout();
+
+        if duration > max {
+            bail!(ErrorKind::TimeoutTooLong(duration, max));
+        }
+
+        Ok(Timeout { duration: duration })
+    }
+}
+
+impl Middleware for Timeout {
+    fn call(&self, req: Request, next: Next) -> HttpFuture {
+        let duration = self.duration;
+
+        let timeout = TIMER.sleep(duration).then(move |timer| -> HttpFuture {
+            match timer {
+                Ok(_) => box finished(ErrorKind::HandlerTimedOut(duration).into()),
+                // Durations are checked up front, so this only happens if
+                // the timer is out of capacity. Fail the request rather
+                // than letting it run without a deadline
+                Err(e) => {
+                    let _ = writeln!(io::stderr(), "timeout couldn't be scheduled: {}", e);
+
+                    box finished(ErrorKind::HandlerTimedOut(duration).into())
+                }
+            }
+        });
+
+        box next.call(req)
+            .select(timeout)
+            .map(|(response, _)| response)
+            .map_err(|(e, _)| e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use errors::ErrorKind;
+    use super::*;
+
+    #[test]
+    fn timeout_within_max_is_ok() {
+        assert!(Timeout::new(Duration::from_secs(30)).is_ok());
+        assert!(Timeout::new(max_timeout()).is_ok());
+    }
+
+    #[test]
+    fn timeout_past_max_is_err() {
+        let err = Timeout::new(max_timeout() + Duration::from_millis(1)).unwrap_err();
+
+        match err.0 {
+            ErrorKind::TimeoutTooLong(..) => (),
+            ref kind => panic!("unexpected error: {:?}", kind),
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! # HTTPS
+//!
+//! `hyper`'s `Server` only knows how to accept plain TCP connections, so
+//! for HTTPS we accept connections ourselves, complete a TLS handshake
+//! using [`rustls`](), and then hand the encrypted stream to `hyper` to
+//! serve like any other connection.
+//!
+//! Whether or not client certificates are requested or required is up to
+//! the `rustls::ServerConfig` that's passed in.
+//!
+//! ## Examples
+//!
+//! ```no_run
+//! # #![feature(associated_consts)]
+//! # extern crate webapp_demo;
+//! # extern crate tokio_core;
+//! # extern crate rustls;
+//! # use std::sync::Arc;
+//! # use tokio_core::reactor::Core;
+//! # use webapp_demo::host::*;
+//! # fn main() {
+//! # let core = Core::new().unwrap();
+//! # let handle = core.handle();
+//! # let tls_config = rustls::ServerConfig::new();
+//! let router = RouterBuilder::new().build().unwrap();
+//!
+//! let addr = "127.0.0.1:1338".parse().unwrap();
+//! let lst = serve_tls(&addr, Arc::new(tls_config), move || Ok(router.clone()), &handle).unwrap();
+//! # }
+//! ```
+
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use futures::{finished, Future, Stream};
+use hyper;
+use hyper::server::{Http, Service};
+use rustls::ServerConfig;
+use tokio_core::net::TcpListener;
+use tokio_core::reactor::{Handle, Timeout};
+use tokio_rustls::ServerConfigExt;
+use super::{Request, Response};
+
+/// How long to stop accepting connections for after accepting fails.
+///
+/// Accepting usually fails because the process is out of file
+/// descriptors, so trying again straight away would just spin.
+const ACCEPT_BACKOFF_MS: u64 = 100;
+
+/// Listen for HTTPS connections on the given address.
+///
+/// A new service is created for each connection using `new_service`, just
+/// like `Server::handle`.
+/// The listener runs on the reactor for `handle`, and this function returns
+/// the address it's listening on.
+///
+/// Failed handshakes, and failures to accept or create a service for a
+/// connection, only affect that connection, so the listener keeps
+/// accepting new connections.
+/// Failures are written to `stderr`.
+pub fn serve_tls<F, S>(addr: &SocketAddr,
+                       config: Arc<ServerConfig>,
+                       new_service: F,
+                       handle: &Handle)
+                       -> io::Result<SocketAddr>
+    where F: Fn() -> io::Result<S> + 'static,
+          S: Service<Request = Request, Response = Response, Error = hyper::Error> + 'static
+{
+    let listener = TcpListener::bind(addr, handle)?;
+    let local_addr = listener.local_addr()?;
+
+    let http = Http::new();
+    let conn_handle = handle.clone();
+
+    // Errors are turned into items so they don't end the stream
+    let incoming = listener.incoming().then(|accepted| -> Result<_, ()> { Ok(accepted) });
+
+    let accept = incoming.for_each(move |accepted| -> Box<Future<Item = (), Error = ()>> {
+        let (socket, remote_addr) = match accepted {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                let _ = writeln!(io::stderr(), "accepting an HTTPS connection failed: {}", e);
+
+                return backoff(&conn_handle);
+            }
+        };
+
+        let service = match new_service() {
+            Ok(service) => service,
+            Err(e) => {
+                let _ = writeln!(io::stderr(), "creating a service for {} failed: {}", remote_addr, e);
+
+                return box finished(());
+            }
+        };
+
+        let http = http.clone();
+        let handle = conn_handle.clone();
+
+        let conn = config.accept_async(socket)
+            .map(move |stream| http.bind_connection(&handle, stream, remote_addr, service))
+            .map_err(move |e| {
+                let _ = writeln!(io::stderr(), "TLS handshake with {} failed: {}", remote_addr, e);
+            });
+
+        conn_handle.spawn(conn);
+
+        box finished(())
+    });
+
+    handle.spawn(accept);
+
+    Ok(local_addr)
+}
+
+/// Wait a moment before accepting any more connections.
+fn backoff(handle: &Handle) -> Box<Future<Item = (), Error = ()>> {
+    match Timeout::new(Duration::from_millis(ACCEPT_BACKOFF_MS), handle) {
+        Ok(timeout) => box timeout.map_err(|_| ()),
+        Err(_) => box finished(()),
+    }
+}
@@ -0,0 +1,179 @@
+//! # Url generation
+//!
+//! Handlers often need to link to other routes, like in a `Location` header
+//! after creating something.
+//! Rather than formatting those paths by hand, routes can be given a name
+//! with `RouterBuilder::name`, and then turned back into a path by filling
+//! in their parameters with `url_for`.
+//!
+//! The names are available to handlers through the `Urls` state, which the
+//! router adds for itself.
+//!
+//! ## Examples
+//!
+//! ```
+//! # #![feature(associated_consts)]
+//! # extern crate webapp_demo;
+//! # use webapp_demo::host::*;
+//! # struct PersonHandler;
+//! # impl Route for PersonHandler {
+//! # const ROUTE: &'static str = "/person/:id";
+//! # }
+//! # impl Get for PersonHandler {
+//! # fn call(&self, _: Params, _: Request) -> HttpFuture {
+//! # unimplemented!()
+//! # }
+//! # }
+//! # fn main() {
+//! let router = RouterBuilder::new()
+//!     .get(PersonHandler)
+//!     .name("person", PersonHandler::ROUTE)
+//!     .build()
+//!     .unwrap();
+//!
+//! let url = router.url_for("person", &[("id", "1")]).unwrap();
+//!
+//! assert_eq!("/person/1", url);
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use url::percent_encoding::{utf8_percent_encode, EncodeSet, DEFAULT_ENCODE_SET, PATH_SEGMENT_ENCODE_SET};
+use errors::*;
+
+/// The bytes to encode in a `*glob` parameter.
+///
+/// This is the same as the set for a path segment, except `/` is left
+/// alone so the glob can match more than one segment.
+#[derive(Clone, Copy)]
+struct GlobEncodeSet;
+
+impl EncodeSet for GlobEncodeSet {
+    fn contains(&self, byte: u8) -> bool {
+        byte == b'%' || DEFAULT_ENCODE_SET.contains(byte)
+    }
+}
+
+/// The named routes of a router.
+#[derive(Debug, Clone, Default)]
+pub struct Urls {
+    routes: Arc<HashMap<String, String>>,
+}
+
+impl Urls {
+    /// Create a set of named routes.
+    pub fn new(routes: HashMap<String, String>) -> Self {
+        Urls { routes: Arc::new(routes) }
+    }
+
+    /// Get the names and route patterns.
+    pub fn routes(&self) -> Vec<(&str, &str)> {
+        self.routes.iter().map(|(name, route)| (&name[..], &route[..])).collect()
+    }
+
+    /// Build the path for a named route by filling in its parameters.
+    ///
+    /// Parameters are percent-encoded, except for the `/` in a `*glob`
+    /// parameter, so the router decodes them back to the same values.
+    /// The name must have been given to a route, and every parameter in
+    /// the route must have a value.
+    pub fn url_for(&self, name: &str, params: &[(&str, &str)]) -> Result<String> {
+        let route = self.routes
+            .get(name)
+            .ok_or_else(|| Error::from(ErrorKind::UnknownRouteName(name.to_owned())))?;
+
+        let find = |param: &str| {
+            params.iter()
+                .find(|&&(key, _)| key == param)
+                .map(|&(_, value)| value)
+                .ok_or_else(|| Error::from(ErrorKind::MissingUrlParam(name.to_owned(), param.to_owned())))
+        };
+
+        let mut segments = Vec::new();
+
+        for segment in route.split('/') {
+            if segment.starts_with(':') {
+                let value = find(&segment[1..])?;
+
+                segments.push(utf8_percent_encode(value, PATH_SEGMENT_ENCODE_SET).to_string());
+            } else if segment.starts_with('*') {
+                let value = find(&segment[1..])?;
+
+                segments.push(utf8_percent_encode(value, GlobEncodeSet).to_string());
+            } else {
+                segments.push(segment.to_owned());
+            }
+        }
+
+        Ok(segments.join("/"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use errors::ErrorKind;
+    use host::path::decode;
+    use super::*;
+
+    fn urls() -> Urls {
+        let mut routes = HashMap::new();
+        routes.insert("person".to_owned(), "/person/:id".to_owned());
+        routes.insert("file".to_owned(), "/files/*path".to_owned());
+
+        Urls::new(routes)
+    }
+
+    #[test]
+    fn param_is_encoded() {
+        let url = urls().url_for("person", &[("id", "a b/c%")]).unwrap();
+
+        assert_eq!("/person/a%20b%2Fc%25", url);
+        assert_eq!("a b/c%", decode(&url["/person/".len()..]));
+    }
+
+    #[test]
+    fn glob_keeps_slashes() {
+        let url = urls().url_for("file", &[("path", "a b/c")]).unwrap();
+
+        assert_eq!("/files/a%20b/c", url);
+    }
+
+    #[test]
+    fn glob_round_trips_percent() {
+        let url = urls().url_for("file", &[("path", "a%20b/c")]).unwrap();
+
+        assert_eq!("/files/a%2520b/c", url);
+        assert_eq!("a%20b/c", decode(&url["/files/".len()..]));
+    }
+
+    #[test]
+    fn missing_param_is_err() {
+        match urls().url_for("person", &[]) {
+            Ok(url) => panic!("expected an error, got {}", url),
+            Err(e) => {
+                match e.0 {
+                    ErrorKind::MissingUrlParam(ref name, ref param) => {
+                        assert_eq!("person", name);
+                        assert_eq!("id", param);
+                    }
+                    ref kind => panic!("unexpected error: {:?}", kind),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_name_is_err() {
+        match urls().url_for("people", &[]) {
+            Ok(url) => panic!("expected an error, got {}", url),
+            Err(e) => {
+                match e.0 {
+                    ErrorKind::UnknownRouteName(ref name) => assert_eq!("people", name),
+                    ref kind => panic!("unexpected error: {:?}", kind),
+                }
+            }
+        }
+    }
+}
@@ -44,17 +44,29 @@
 //! routing.
 //! This infrastructure lives in the `host` module.
 
-#![feature(box_syntax, associated_consts, proc_macro)]
+#![feature(box_syntax, associated_consts, associated_type_defaults, try_from, proc_macro, fnbox)]
 
 #[macro_use]
 extern crate serde_derive;
 
+#[macro_use]
 extern crate serde;
+extern crate serde_json;
 
 extern crate futures;
+extern crate tokio_core;
+#[macro_use]
 extern crate hyper;
 extern crate route_recognizer;
+extern crate url;
 extern crate tokio_timer;
+extern crate tokio_rustls;
+extern crate rustls;
+extern crate prometheus;
+extern crate backtrace;
+
+#[macro_use]
+extern crate lazy_static;
 
 #[macro_use]
 extern crate error_chain;
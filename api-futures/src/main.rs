@@ -6,26 +6,31 @@ extern crate serde_json;
 extern crate futures;
 extern crate futures_cpupool;
 extern crate tokio_core;
+extern crate tokio_signal;
 extern crate tokio_timer;
 extern crate hyper;
 extern crate route_recognizer;
 extern crate webapp_demo;
+extern crate webapp_config;
 
+use std::io::{self, Write};
+use std::process;
+use std::sync::Arc;
 use std::time::Duration;
-use futures::{Future, finished, lazy};
+use futures::{Future, Stream, finished, lazy};
 use futures_cpupool::CpuPool;
-use tokio_core::reactor::Core;
+use tokio_core::reactor::{Core, Timeout};
+use tokio_signal::unix::{Signal, SIGTERM};
 use tokio_timer::Timer;
 use hyper::header::ContentLength;
 use webapp_demo::host::*;
+use webapp_config::Config;
 
 /// A handler for a HTTP request.
 /// 
 /// This handler implements `Route + Get + Post`, so can
 /// be used to handle either of those verbs.
-struct MyHandler {
-    cpu_pool: CpuPool,
-}
+struct MyHandler;
 
 impl Route for MyHandler {
     const ROUTE: &'static str = "/";
@@ -44,9 +49,12 @@ impl Get for MyHandler {
 
 // 'POST /'
 impl Post for MyHandler {
-    fn call(&self, _: Params, _: Request) -> HttpFuture {
+    // The background worker pool is shared by the router
+    type Params = State<CpuPool>;
+
+    fn call(&self, cpu_pool: State<CpuPool>, _: Request) -> HttpFuture {
         // Do some 'expensive work' on a background thread
-        let work = self.cpu_pool
+        let work = cpu_pool
             .spawn(lazy(|| {
                 Timer::default()
                     .sleep(Duration::from_millis(1000))
@@ -67,26 +75,86 @@ impl Post for MyHandler {
 }
 
 fn main() {
+    // Log panics in handlers with a backtrace.
+    log_panics();
+
+    // Load the config from the command line, environment and config file.
+    let config = match Config::load("webapp_demo_host") {
+        Ok(config) => config,
+        Err(e) => {
+            let _ = writeln!(io::stderr(), "error: {}", e);
+            process::exit(1);
+        }
+    };
+
     // Create a background worker pool.
-    let cpu_pool = CpuPool::new(4);
+    let cpu_pool = CpuPool::new(config.limits.workers);
 
     // Create a request router with our handlers.
     let router = RouterBuilder::new()
-        .get(MyHandler { cpu_pool: cpu_pool.clone() })
-        .post(MyHandler { cpu_pool: cpu_pool.clone() })
-        .build();
+        .with_state(cpu_pool)
+        .concurrency_limit(config.limits.max_in_flight, config.limits.max_queued)
+        // Requests to `MyHandler` can hand work to the pool, so don't let
+        // more of them pile up than it can get through
+        .route_concurrency_limit(MyHandler::ROUTE, config.limits.workers, config.limits.workers * 4)
+        .get(MyHandler)
+        .post(MyHandler)
+        .get(Metrics)
+        .build()
+        .unwrap_or_else(|e| {
+            let _ = writeln!(io::stderr(), "error: {}", e);
+            process::exit(1);
+        });
 
     // Create a `tokio` reactor.
     let mut core = Core::new().unwrap();
     let handle = core.handle();
 
     // Set up our server to run on the reactor.
-    let addr = "127.0.0.1:1337".parse().unwrap();
+    // The router is wrapped in a service that keeps track of in-flight
+    // requests so we can let them finish when shutting down.
+    let service = Drain::new(router);
+    let drain = service.handle();
+
+    let addr = config.server.addr().unwrap();
     let server = Server::http(&addr).unwrap();
-    let lst = server.handle(move || Ok(router.clone()), &handle).unwrap();
+    let http_service = service.clone();
+    let lst = server.handle(move || Ok(http_service.clone()), &handle).unwrap();
 
     println!("listening on {}", lst);
 
-    // Run the server 4eva
-    core.run(futures::empty::<(), ()>()).unwrap();
+    // If there's a TLS config then also listen for HTTPS connections.
+    if let Some(ref tls) = config.tls {
+        let tls_addr = tls.addr().unwrap();
+        let tls_config = Arc::new(tls.server_config().unwrap());
+
+        let tls_lst = serve_tls(&tls_addr, tls_config, move || Ok(service.clone()), &handle).unwrap();
+
+        println!("listening for https on {}", tls_lst);
+    }
+
+    // Run the server until we're asked to stop with either SIGINT or SIGTERM.
+    let ctrl_c = tokio_signal::ctrl_c(&handle).flatten_stream();
+    let sigterm = Signal::new(SIGTERM, &handle).flatten_stream().map(|_| ());
+
+    let signal = ctrl_c.select(sigterm).into_future();
+    core.run(signal).map_err(|(e, _)| e).unwrap();
+
+    // Stop accepting new requests and wait for in-flight ones to finish.
+    // If they don't finish before the deadline then we give up on them.
+    println!("shutting down with {} requests in flight", drain.in_flight());
+    drain.start();
+
+    let drained = drain.drained(&handle).map(|_| true);
+    let deadline = Timeout::new(config.server.shutdown_timeout(), &handle)
+        .unwrap()
+        .map(|_| false);
+
+    match core.run(drained.select(deadline)) {
+        Ok((true, _)) => println!("all requests finished"),
+        Ok((false, _)) => {
+            println!("gave up waiting for {} requests to finish", drain.in_flight())
+        }
+        Err((e, _)) => println!("failed waiting for requests to finish: {}", e),
+    }
 }
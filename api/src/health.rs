@@ -0,0 +1,246 @@
+//! # Health checks
+//!
+//! The application exposes two endpoints for whatever is orchestrating it:
+//!
+//! - `healthz` handles `GET /healthz`, and returns a `HTTP 200` as long as
+//! the process is able to serve requests at all.
+//! - `readyz` handles `GET /readyz`, and checks the dependencies we need
+//! to do useful work. Right now that's just Redis. If any dependency is
+//! down we return a `HTTP 503` along with a json breakdown of the checks.
+//!
+//! Redis is checked through the same connection path as the request
+//! handlers, so a passing check means handlers can connect too.
+//! The check runs on a background thread so a Redis instance that's
+//! accepting connections but not responding can't hold up the probe
+//! for longer than `REDIS_TIMEOUT_MS`.
+//! Only one check runs at a time; probes that arrive while a check is
+//! running wait for its result instead of starting another thread, so a
+//! hung Redis can't pile up threads.
+
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde_json;
+use redis;
+use iron::prelude::*;
+use iron::status;
+use iron::headers::ContentType;
+use iron::modifiers::Header;
+use webapp_config::RedisConfig;
+
+use errors::*;
+use metrics;
+use routes;
+
+/// The maximum time to wait for Redis to respond to a `PING`.
+const REDIS_TIMEOUT_MS: u64 = 1000;
+
+lazy_static! {
+    static ref REDIS_CHECK: RedisCheck = RedisCheck {
+        state: Mutex::new(RedisCheckState {
+            running: false,
+            finished: 0,
+            last: None,
+        }),
+        done: Condvar::new(),
+    };
+}
+
+/// The Redis check shared by all probes.
+struct RedisCheck {
+    state: Mutex<RedisCheckState>,
+    done: Condvar,
+}
+
+struct RedisCheckState {
+    /// Whether a check is running on a background thread.
+    running: bool,
+    /// The number of checks that have finished.
+    finished: u64,
+    /// The result of the last check to finish.
+    last: Option<Check>,
+}
+
+/// The result of checking whether the application can serve requests.
+#[derive(Debug, PartialEq, Serialize)]
+struct Readiness {
+    status: Status,
+    checks: Checks,
+}
+
+/// The results of checking individual dependencies.
+#[derive(Debug, PartialEq, Serialize)]
+struct Checks {
+    redis: Check,
+}
+
+/// The result of checking a single dependency.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+struct Check {
+    status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+enum Status {
+    #[serde(rename = "up")]
+    Up,
+    #[serde(rename = "down")]
+    Down,
+}
+
+impl Check {
+    fn up() -> Self {
+        Check {
+            status: Status::Up,
+            error: None,
+        }
+    }
+
+    fn down<E: Into<String>>(err: E) -> Self {
+        Check {
+            status: Status::Down,
+            error: Some(err.into()),
+        }
+    }
+}
+
+impl Readiness {
+    fn new(checks: Checks) -> Self {
+        let status = checks.redis.status;
+
+        Readiness {
+            status: status,
+            checks: checks,
+        }
+    }
+}
+
+/// Check the process is alive.
+pub fn get_healthz(_: &mut Request) -> IronResult<Response> {
+    Ok(Response::with((status::Ok, "ok")))
+}
+
+/// Check the application's dependencies are available.
+///
+/// This handler returns a `HTTP 200` if all dependencies are up, or a
+/// `HTTP 503` if any of them are down.
+pub fn get_readyz(req: &mut Request) -> IronResult<Response> {
+    let redis_config = routes::get_redis_config(req);
+
+    let readiness = Readiness::new(Checks { redis: check_redis(redis_config) });
+
+    let code = match readiness.status {
+        Status::Up => status::Ok,
+        Status::Down => status::ServiceUnavailable,
+    };
+
+    let body = serde_json::to_string(&readiness).map_err(Error::from)?;
+
+    Ok(Response::with((code, Header(ContentType::json()), body)))
+}
+
+/// Check Redis responds to a `PING` within the timeout.
+///
+/// If a check is already running then this waits for its result rather
+/// than starting a new one.
+fn check_redis(config: Arc<RedisConfig>) -> Check {
+    let timeout = Duration::from_millis(REDIS_TIMEOUT_MS);
+    let deadline = Instant::now() + timeout;
+
+    let mut state = REDIS_CHECK.state.lock().unwrap();
+
+    // Wait for the running check, or the one we're about to start
+    let target = state.finished + 1;
+
+    if !state.running {
+        state.running = true;
+
+        thread::spawn(move || {
+            let check = match routes::get_conn(&config).and_then(|conn| ping(&conn, timeout)) {
+                Ok(()) => Check::up(),
+                Err(e) => Check::down(e.to_string()),
+            };
+
+            let mut state = REDIS_CHECK.state.lock().unwrap();
+
+            state.running = false;
+            state.finished += 1;
+            state.last = Some(check);
+
+            REDIS_CHECK.done.notify_all();
+        });
+    }
+
+    while state.finished < target {
+        let now = Instant::now();
+
+        if now >= deadline {
+            return Check::down(format!("no response within {}ms", REDIS_TIMEOUT_MS));
+        }
+
+        state = REDIS_CHECK.done.wait_timeout(state, deadline - now).unwrap().0;
+    }
+
+    state.last.clone().expect("a finished check should have a result")
+}
+
+/// Send a `PING` to Redis.
+///
+/// The connection is given read and write timeouts so a Redis instance
+/// that accepts connections but never responds can't block the check
+/// forever.
+fn ping(conn: &redis::Connection, timeout: Duration) -> Result<()> {
+    conn.set_read_timeout(Some(timeout))?;
+    conn.set_write_timeout(Some(timeout))?;
+
+    metrics::redis_call("ping", || {
+        let _: String = redis::cmd("PING").query(conn)?;
+
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+    use super::*;
+
+    #[test]
+    fn serialise_readiness_up() {
+        let readiness = Readiness::new(Checks { redis: Check::up() });
+
+        let expected = json_str!({
+            "status": "up",
+            "checks": {
+                "redis": {
+                    "status": "up"
+                }
+            }
+        });
+
+        let result = serde_json::to_string(&readiness).unwrap();
+
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn serialise_readiness_down() {
+        let readiness = Readiness::new(Checks { redis: Check::down("connection refused") });
+
+        let expected = json_str!({
+            "status": "down",
+            "checks": {
+                "redis": {
+                    "status": "down",
+                    "error": "connection refused"
+                }
+            }
+        });
+
+        let result = serde_json::to_string(&readiness).unwrap();
+
+        assert_eq!(expected, result);
+    }
+}
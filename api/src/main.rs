@@ -8,14 +8,25 @@ extern crate json_str;
 
 extern crate iron;
 extern crate router;
+extern crate hyper_rustls;
+extern crate persistent;
+
+extern crate chan;
+extern crate chan_signal;
 
 extern crate redis;
 
+#[macro_use]
+extern crate lazy_static;
+extern crate prometheus;
+
 #[macro_use]
 extern crate serde_derive;
 extern crate serde;
 extern crate serde_json;
 
+extern crate webapp_config;
+
 /// Error types.
 pub mod errors;
 
@@ -25,19 +36,101 @@ pub mod model;
 /// Web handler routes.
 pub mod routes;
 
+/// Request and Redis metrics.
+pub mod metrics;
+
+/// Health and readiness checks.
+pub mod health;
+
+/// Graceful shutdown.
+pub mod shutdown;
+
+use std::io::{self, Write};
+use std::process;
+use std::sync::Arc;
+use hyper_rustls::TlsServer;
 use iron::prelude::*;
+use persistent::Read;
 use router::Router;
+use webapp_config::Config;
+use shutdown::Drain;
 
 fn main() {
+    // Listen for shutdown signals before any other threads are started
+    let signal = shutdown::listen();
+
+    // Load the config from the command line, environment and config file
+    let config = match Config::load("webapp_demo") {
+        Ok(config) => config,
+        Err(e) => {
+            let _ = writeln!(io::stderr(), "error: {}", e);
+            process::exit(1);
+        }
+    };
+
     // Create a new Iron router
     let mut router = Router::new();
 
     // Get a person by id
-    router.get("/person/:id", routes::get_person, "get_person");
+    router.get("/person/:id",
+               metrics::instrument("get_person", routes::get_person),
+               "get_person");
 
     // Post an updated person value
-    router.post("/person/:id", routes::post_person, "post_person");
+    router.post("/person/:id",
+                metrics::instrument("post_person", routes::post_person),
+                "post_person");
+
+    // Get the collected metrics
+    router.get("/metrics", metrics::get_metrics, "get_metrics");
+
+    // Check the process is alive, and whether its dependencies are
+    router.get("/healthz", health::get_healthz, "get_healthz");
+    router.get("/readyz", health::get_readyz, "get_readyz");
+
+    // Share the Redis config with our handlers
+    let mut chain = Chain::new(router);
+    chain.link_before(Read::<routes::Redis>::one(config.redis.clone()));
+
+    // Keep track of in-flight requests so we can let them finish
+    let app = Drain::new(chain);
+    let drain = app.handle();
 
     // Create the Iron server with the router and start listening
-    Iron::new(router).http("localhost:1337").unwrap();
+    let mut iron = Iron::new(app.clone());
+    iron.threads = config.limits.workers;
+
+    let mut listening = iron.http(config.server.addr().unwrap()).unwrap();
+
+    // If there's a TLS config then also listen for HTTPS connections
+    let mut tls_listening = config.tls.as_ref().map(|tls| {
+        let tls_server = TlsServer { cfg: Arc::new(tls.server_config().unwrap()) };
+
+        let mut iron = Iron::new(app.clone());
+        iron.threads = config.limits.workers;
+
+        iron.https(tls.addr().unwrap(), tls_server).unwrap()
+    });
+
+    // Wait until we're asked to stop, then let in-flight requests finish
+    signal.wait();
+
+    println!("shutting down with {} requests in flight", drain.in_flight());
+    drain.start();
+
+    if drain.wait(config.server.shutdown_timeout()) {
+        println!("all requests finished");
+    } else {
+        println!("gave up waiting for {} requests to finish", drain.in_flight());
+    }
+
+    let _ = listening.close();
+
+    if let Some(ref mut tls_listening) = tls_listening {
+        let _ = tls_listening.close();
+    }
+
+    // Dropping the listener would wait for its threads, which may still be
+    // blocked on idle connections, so we exit straight away.
+    process::exit(0);
 }
@@ -0,0 +1,148 @@
+//! # Metrics
+//!
+//! We use the [`prometheus`]() crate to collect metrics about the requests
+//! we handle and the calls we make to Redis.
+//!
+//! Iron's `router` doesn't tell us which route a request matched, so each
+//! handler is wrapped in an `Instrumented` handler that knows its route id.
+//! Labelling by route id rather than the raw path keeps the number of label
+//! values small; `/person/1` and `/person/2` both count towards `get_person`.
+//!
+//! Redis calls are timed and counted by wrapping them in `redis_call`.
+
+use std::time::{Duration, Instant};
+use prometheus::{self, CounterVec, Encoder, HistogramOpts, HistogramVec, Opts, TextEncoder};
+use iron::prelude::*;
+use iron::{status, Handler};
+use iron::headers::ContentType;
+use iron::modifiers::Header;
+
+use errors::*;
+
+lazy_static! {
+    static ref HTTP_REQUESTS_TOTAL: CounterVec = {
+        let counter = CounterVec::new(Opts::new("http_requests_total",
+                                                "Total number of HTTP requests handled."),
+                                      &["route", "method", "status"])
+            .unwrap();
+
+        prometheus::register(Box::new(counter.clone())).unwrap();
+
+        counter
+    };
+
+    static ref HTTP_REQUEST_DURATION_SECONDS: HistogramVec = {
+        let histogram = HistogramVec::new(HistogramOpts::new("http_request_duration_seconds",
+                                                             "HTTP request latency in seconds."),
+                                          &["route", "method", "status"])
+            .unwrap();
+
+        prometheus::register(Box::new(histogram.clone())).unwrap();
+
+        histogram
+    };
+
+    static ref REDIS_CALL_DURATION_SECONDS: HistogramVec = {
+        let histogram = HistogramVec::new(HistogramOpts::new("redis_call_duration_seconds",
+                                                             "Redis call latency in seconds."),
+                                          &["operation"])
+            .unwrap();
+
+        prometheus::register(Box::new(histogram.clone())).unwrap();
+
+        histogram
+    };
+
+    static ref REDIS_CALL_ERRORS_TOTAL: CounterVec = {
+        let counter = CounterVec::new(Opts::new("redis_call_errors_total",
+                                                "Total number of failed Redis calls."),
+                                      &["operation"])
+            .unwrap();
+
+        prometheus::register(Box::new(counter.clone())).unwrap();
+
+        counter
+    };
+}
+
+/// A handler that records metrics for the handler it wraps.
+pub struct Instrumented<H> {
+    route: &'static str,
+    handler: H,
+}
+
+/// Wrap a handler so its requests are recorded under the given route id.
+///
+/// The route id should be the same one given to Iron's `router`:
+///
+/// ```ignore
+/// router.get("/person/:id", metrics::instrument("get_person", routes::get_person), "get_person");
+/// ```
+pub fn instrument<H: Handler>(route: &'static str, handler: H) -> Instrumented<H> {
+    Instrumented {
+        route: route,
+        handler: handler,
+    }
+}
+
+impl<H: Handler> Handler for Instrumented<H> {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        let start = Instant::now();
+        let method = req.method.to_string();
+
+        let res = self.handler.handle(req);
+
+        let status = match res {
+            Ok(ref res) => res.status,
+            Err(ref err) => err.response.status,
+        };
+        let status = status.map(|status| status.to_u16().to_string())
+            .unwrap_or_else(|| "unknown".to_owned());
+
+        let labels = [self.route, &method[..], &status[..]];
+
+        HTTP_REQUESTS_TOTAL.with_label_values(&labels).inc();
+        HTTP_REQUEST_DURATION_SECONDS.with_label_values(&labels).observe(as_secs(start.elapsed()));
+
+        res
+    }
+}
+
+/// Time a call to Redis, counting it as an error if it fails.
+pub fn redis_call<T, F>(operation: &str, f: F) -> Result<T>
+    where F: FnOnce() -> Result<T>
+{
+    let start = Instant::now();
+
+    let res = f();
+
+    REDIS_CALL_DURATION_SECONDS.with_label_values(&[operation]).observe(as_secs(start.elapsed()));
+
+    if res.is_err() {
+        REDIS_CALL_ERRORS_TOTAL.with_label_values(&[operation]).inc();
+    }
+
+    res
+}
+
+fn as_secs(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1_000_000_000f64
+}
+
+/// Serve the collected metrics.
+///
+/// Metrics are rendered in the Prometheus text exposition format.
+pub fn get_metrics(_: &mut Request) -> IronResult<Response> {
+    let encoder = TextEncoder::new();
+    let metric_families = prometheus::gather();
+
+    let mut buffer = Vec::new();
+    match encoder.encode(&metric_families, &mut buffer) {
+        Ok(()) => {
+            let content_type = ContentType(encoder.format_type().parse().unwrap());
+
+            Ok(Response::with((status::Ok, Header(content_type), buffer)))
+        }
+        Err(_) => Ok(Response::with(status::InternalServerError)),
+    }
+}
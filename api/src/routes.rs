@@ -9,14 +9,19 @@
 //! - `post_person` handles `POST /person/:id`, and will update a
 //! `Person` in Redis with a new name.
 
+use std::sync::Arc;
 use serde_json;
 use redis::{self, Commands};
 use iron::prelude::*;
 use iron::status;
+use iron::typemap::Key;
+use persistent::Read;
 use router::Router;
+use webapp_config::RedisConfig;
 
 use errors::*;
 use model::*;
+use metrics;
 
 /// Get a person by id.
 ///
@@ -24,7 +29,7 @@ use model::*;
 /// the corresponding person, or returns a `HTTP 404`.
 pub fn get_person(req: &mut Request) -> IronResult<Response> {
     let id = get_id(&req)?;
-    let conn = get_conn()?;
+    let conn = get_conn(&get_redis_config(req))?;
 
     let person_data = get_person_data(conn, &id)?;
 
@@ -48,7 +53,7 @@ struct PostPersonCommand {
 /// ```
 pub fn post_person(req: &mut Request) -> IronResult<Response> {
     let id = get_id(&req)?;
-    let conn = get_conn()?;
+    let conn = get_conn(&get_redis_config(req))?;
 
     let person = make_person(req, id)?;
 
@@ -67,15 +72,36 @@ fn get_id(req: &Request) -> Result<Id> {
         .try_into()
 }
 
+/// The Redis config shared with every request.
+///
+/// The config is added to the app with [`persistent`]() so each handler
+/// gets a cheap reference counted pointer to it.
+pub struct Redis;
+
+impl Key for Redis {
+    type Value = RedisConfig;
+}
+
+/// Get the Redis config for the app.
+pub fn get_redis_config(req: &mut Request) -> Arc<RedisConfig> {
+    req.get::<Read<Redis>>()
+        .expect("the Redis config should be added to the app")
+}
+
 /// Get a new Redis connection.
-fn get_conn() -> Result<redis::Connection> {
-    let client = redis::Client::open("redis://127.0.0.1/")?;
-    client.get_connection().map_err(|e| e.into())
+pub fn get_conn(config: &RedisConfig) -> Result<redis::Connection> {
+    metrics::redis_call("connect", || {
+        let client = redis::Client::open(&config.url[..])?;
+        client.get_connection().map_err(|e| e.into())
+    })
 }
 
 /// Get the data for a `Person` from Redis.
 fn get_person_data(conn: redis::Connection, id: &Id) -> Result<String> {
-    let person_data: Option<String> = conn.get(id.as_ref())?;
+    let person_data: Option<String> = metrics::redis_call("get", || {
+        conn.get(id.as_ref()).map_err(|e| e.into())
+    })?;
+
     person_data.ok_or(Error::from(ErrorKind::PersonNotFound))
 }
 
@@ -83,9 +109,9 @@ fn get_person_data(conn: redis::Connection, id: &Id) -> Result<String> {
 fn set_person_data(conn: redis::Connection, person: Person) -> Result<()> {
     let person_data = serde_json::to_string(&person)?;
 
-    conn.set(person.id.as_ref(), person_data)?;
-
-    Ok(())
+    metrics::redis_call("set", || {
+        conn.set(person.id.as_ref(), person_data).map_err(|e| e.into())
+    })
 }
 
 /// Get a person from the request body with an id.
@@ -0,0 +1,147 @@
+//! # Graceful shutdown
+//!
+//! When the app is asked to stop with `SIGINT` or `SIGTERM` we don't want to
+//! drop requests that are already being processed.
+//! The `Drain` handler wraps the rest of the app and keeps count of the
+//! requests it has in flight.
+//!
+//! Once draining has started, new requests are turned away with a
+//! `HTTP 503` and `Connection: close`, so clients and load balancers know
+//! to go elsewhere, while existing requests are left to finish.
+//! When there are no requests left, or the deadline passes, we stop the
+//! server.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use chan_signal::{self, Signal};
+use iron::prelude::*;
+use iron::{status, Handler};
+use iron::headers::Connection;
+use iron::modifiers::Header;
+
+/// How often to check whether in-flight requests have finished.
+const DRAIN_POLL_MS: u64 = 50;
+
+/// A handler that tracks in-flight requests so they can be drained.
+///
+/// This handler is cheap to clone, so the same app can be served by
+/// more than one listener. All clones share the same count of in-flight
+/// requests.
+pub struct Drain<H> {
+    handler: Arc<H>,
+    state: Arc<DrainState>,
+}
+
+impl<H> Clone for Drain<H> {
+    fn clone(&self) -> Self {
+        Drain {
+            handler: self.handler.clone(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+/// A handle for draining the requests of a `Drain` handler.
+#[derive(Clone)]
+pub struct DrainHandle {
+    state: Arc<DrainState>,
+}
+
+struct DrainState {
+    draining: AtomicBool,
+    in_flight: AtomicUsize,
+}
+
+/// Marks a single request as in flight until it's dropped.
+///
+/// Using a guard means a request stops being counted even if its
+/// handler panics.
+struct InFlight<'a>(&'a DrainState);
+
+impl<'a> Drop for InFlight<'a> {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl<H: Handler> Drain<H> {
+    /// Wrap a handler so its requests can be drained.
+    pub fn new(handler: H) -> Self {
+        Drain {
+            handler: Arc::new(handler),
+            state: Arc::new(DrainState {
+                draining: AtomicBool::new(false),
+                in_flight: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Get a handle for draining this handler.
+    pub fn handle(&self) -> DrainHandle {
+        DrainHandle { state: self.state.clone() }
+    }
+}
+
+impl<H: Handler> Handler for Drain<H> {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        if self.state.draining.load(Ordering::SeqCst) {
+            return Ok(Response::with((status::ServiceUnavailable, Header(Connection::close()))));
+        }
+
+        self.state.in_flight.fetch_add(1, Ordering::SeqCst);
+        let _in_flight = InFlight(&self.state);
+
+        self.handler.handle(req)
+    }
+}
+
+impl DrainHandle {
+    /// Start draining.
+    ///
+    /// Any requests received after this point are rejected.
+    pub fn start(&self) {
+        self.state.draining.store(true, Ordering::SeqCst);
+    }
+
+    /// The number of requests currently in flight.
+    pub fn in_flight(&self) -> usize {
+        self.state.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Block until there are no requests in flight, or the timeout passes.
+    ///
+    /// Returns `true` if all requests finished in time.
+    pub fn wait(&self, timeout: Duration) -> bool {
+        let start = Instant::now();
+
+        while self.in_flight() > 0 {
+            if start.elapsed() >= timeout {
+                return false;
+            }
+
+            thread::sleep(Duration::from_millis(DRAIN_POLL_MS));
+        }
+
+        true
+    }
+}
+
+/// Listen for `SIGINT` and `SIGTERM`.
+///
+/// This needs to be called before any other threads are started, so they
+/// inherit the signal mask and don't get killed by the signal themselves.
+pub fn listen() -> ShutdownSignal {
+    ShutdownSignal(chan_signal::notify(&[Signal::INT, Signal::TERM]))
+}
+
+/// A signal that the app should shut down.
+pub struct ShutdownSignal(::chan::Receiver<Signal>);
+
+impl ShutdownSignal {
+    /// Block until a shutdown signal is received.
+    pub fn wait(self) -> Option<Signal> {
+        self.0.recv()
+    }
+}
@@ -0,0 +1,725 @@
+//! # Configuration
+//!
+//! Both the `api` and `api-futures` servers are configured the same way,
+//! so their configuration lives in this shared crate.
+//!
+//! Configuration is built up in layers, where later layers override
+//! earlier ones:
+//!
+//! 1. Defaults, which are good enough to run the app locally
+//! 2. A [toml]() file, given by `--config <FILE>` or `WEBAPP_CONFIG`
+//! 3. Environment variables, like `WEBAPP_BIND`
+//! 4. Command line flags, like `--bind`
+//!
+//! The final configuration is validated before it's handed to the app,
+//! so a bad value is reported at startup instead of when it's first used.
+//!
+//! ## Examples
+//!
+//! A config file with every value set looks like:
+//!
+//! ```toml
+//! [server]
+//! bind = "127.0.0.1:1337"
+//! shutdown_timeout_secs = 30
+//!
+//! [redis]
+//! url = "redis://127.0.0.1/"
+//!
+//! [limits]
+//! workers = 4
+//! max_in_flight = 1024
+//! max_queued = 256
+//! ```
+//!
+//! Any of the tables or values can be left out to use the defaults.
+//! An HTTPS listener is only started if there's a `[tls]` table; see the
+//! `TlsConfig` for details.
+
+#![feature(proc_macro)]
+
+#[macro_use]
+extern crate serde_derive;
+extern crate serde;
+
+extern crate toml;
+extern crate clap;
+extern crate rustls;
+
+#[macro_use]
+extern crate error_chain;
+
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::Path;
+use std::time::Duration;
+use serde::Deserialize;
+use clap::{App, Arg, ArgMatches};
+
+/// Configuration errors.
+pub mod errors {
+    use std::io;
+
+    error_chain! {
+        foreign_links {
+            io::Error, Io;
+        }
+
+        errors {
+            Parse(source: String, msg: String) {
+                description("the config file couldn't be parsed")
+                display("failed to parse config file '{}': {}", source, msg)
+            }
+            Invalid(key: String, msg: String) {
+                description("a config value is invalid")
+                display("invalid value for '{}': {}", key, msg)
+            }
+        }
+    }
+}
+
+use errors::*;
+
+mod tls;
+
+pub use tls::TlsConfig;
+
+/// The environment variable for the path to a config file.
+pub const CONFIG_ENV: &'static str = "WEBAPP_CONFIG";
+
+/// The environment variable overriding `server.bind`.
+pub const BIND_ENV: &'static str = "WEBAPP_BIND";
+
+/// The environment variable overriding `redis.url`.
+pub const REDIS_URL_ENV: &'static str = "WEBAPP_REDIS_URL";
+
+/// The environment variable overriding `server.shutdown_timeout_secs`.
+pub const SHUTDOWN_TIMEOUT_ENV: &'static str = "WEBAPP_SHUTDOWN_TIMEOUT_SECS";
+
+/// The environment variable overriding `limits.workers`.
+pub const WORKERS_ENV: &'static str = "WEBAPP_WORKERS";
+
+/// The environment variable overriding `limits.max_in_flight`.
+pub const MAX_IN_FLIGHT_ENV: &'static str = "WEBAPP_MAX_IN_FLIGHT";
+
+/// The environment variable overriding `limits.max_queued`.
+pub const MAX_QUEUED_ENV: &'static str = "WEBAPP_MAX_QUEUED";
+
+/// The environment variable overriding `tls.bind`.
+pub const TLS_BIND_ENV: &'static str = "WEBAPP_TLS_BIND";
+
+/// The environment variable overriding `tls.cert`.
+pub const TLS_CERT_ENV: &'static str = "WEBAPP_TLS_CERT";
+
+/// The environment variable overriding `tls.key`.
+pub const TLS_KEY_ENV: &'static str = "WEBAPP_TLS_KEY";
+
+/// The environment variable overriding `tls.client_ca`.
+pub const TLS_CLIENT_CA_ENV: &'static str = "WEBAPP_TLS_CLIENT_CA";
+
+/// The environment variable overriding `tls.require_client_cert`.
+pub const TLS_REQUIRE_CLIENT_CERT_ENV: &'static str = "WEBAPP_TLS_REQUIRE_CLIENT_CERT";
+
+/// The complete application configuration.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub server: ServerConfig,
+    #[serde(default)]
+    pub redis: RedisConfig,
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    /// Configuration for an optional HTTPS listener.
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+}
+
+/// Configuration for the HTTP server.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ServerConfig {
+    /// The address to listen on, like `127.0.0.1:1337`.
+    #[serde(default = "default_bind")]
+    pub bind: String,
+    /// The number of seconds to wait for in-flight requests to finish
+    /// when shutting down.
+    #[serde(default = "default_shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: u64,
+}
+
+fn default_bind() -> String {
+    "127.0.0.1:1337".to_owned()
+}
+
+fn default_shutdown_timeout_secs() -> u64 {
+    30
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind: default_bind(),
+            shutdown_timeout_secs: default_shutdown_timeout_secs(),
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Resolve the address to listen on.
+    ///
+    /// If the address resolves to more than one socket address, like
+    /// `localhost` might, then the first one is used.
+    pub fn addr(&self) -> Result<SocketAddr> {
+        let mut addrs = self.bind
+            .to_socket_addrs()
+            .chain_err(|| invalid("server.bind", format!("'{}' isn't a valid address", self.bind)))?;
+
+        match addrs.next() {
+            Some(addr) => Ok(addr),
+            None => bail!(invalid("server.bind", format!("'{}' doesn't resolve to any addresses", self.bind))),
+        }
+    }
+
+    /// The time to wait for in-flight requests to finish when shutting down.
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.shutdown_timeout_secs)
+    }
+}
+
+/// Configuration for the Redis connection.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RedisConfig {
+    /// The url to connect to, like `redis://127.0.0.1/`.
+    pub url: String,
+}
+
+impl Default for RedisConfig {
+    fn default() -> Self {
+        RedisConfig { url: "redis://127.0.0.1/".to_owned() }
+    }
+}
+
+/// Limits on the resources used by the app.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct LimitsConfig {
+    /// The number of threads used to do work.
+    ///
+    /// For `api` this is the number of request threads, and for
+    /// `api-futures` it's the size of the background `CpuPool`.
+    #[serde(default = "default_workers")]
+    pub workers: usize,
+    /// The number of requests that can be handled at once.
+    ///
+    /// This is only used by `api-futures`, where requests aren't bounded
+    /// by the number of threads.
+    #[serde(default = "default_max_in_flight")]
+    pub max_in_flight: usize,
+    /// The number of requests that can wait for one of the `max_in_flight`
+    /// slots to free up before new ones are turned away.
+    #[serde(default = "default_max_queued")]
+    pub max_queued: usize,
+}
+
+fn default_workers() -> usize {
+    4
+}
+
+fn default_max_in_flight() -> usize {
+    1024
+}
+
+fn default_max_queued() -> usize {
+    256
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        LimitsConfig {
+            workers: default_workers(),
+            max_in_flight: default_max_in_flight(),
+            max_queued: default_max_queued(),
+        }
+    }
+}
+
+impl Config {
+    /// Load the configuration for the current process.
+    ///
+    /// This reads the command line and environment, along with a config
+    /// file if one is given.
+    /// Invalid command line flags will print a usage message and exit the
+    /// process.
+    pub fn load(name: &str) -> Result<Self> {
+        let matches = app(name).get_matches();
+
+        Self::from_matches(&matches, env::vars())
+    }
+
+    /// Load the configuration from the given command line matches and
+    /// environment variables.
+    fn from_matches<I>(matches: &ArgMatches, vars: I) -> Result<Self>
+        where I: IntoIterator<Item = (String, String)>
+    {
+        let vars: Vec<(String, String)> = vars.into_iter().collect();
+
+        let path = matches.value_of("config")
+            .map(|path| path.to_owned())
+            .or_else(|| find_var(&vars, CONFIG_ENV).map(|path| path.to_owned()));
+
+        let mut config = match path {
+            Some(path) => Self::from_file(path)?,
+            None => Config::default(),
+        };
+
+        config.apply_env(&vars)?;
+        config.apply_matches(matches)?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Read the configuration from a toml file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+
+        let mut file = File::open(path).chain_err(|| {
+                ErrorKind::Parse(path.display().to_string(), "the file couldn't be opened".to_owned())
+            })?;
+
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+
+        Self::from_toml(&path.display().to_string(), &contents)
+    }
+
+    /// Read the configuration from a toml string.
+    ///
+    /// The `source` is used to describe where the string came from in
+    /// error messages.
+    pub fn from_toml(source: &str, toml: &str) -> Result<Self> {
+        let mut parser = toml::Parser::new(toml);
+
+        let table = match parser.parse() {
+            Some(table) => table,
+            None => {
+                let msg = parser.errors
+                    .iter()
+                    .map(|e| {
+                        let (line, col) = parser.to_linecol(e.lo);
+                        format!("{} at line {} column {}", e.desc, line + 1, col + 1)
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                bail!(ErrorKind::Parse(source.to_owned(), msg));
+            }
+        };
+
+        let mut decoder = toml::Decoder::new(toml::Value::Table(table));
+
+        Config::deserialize(&mut decoder)
+            .map_err(|e| ErrorKind::Parse(source.to_owned(), e.to_string()).into())
+    }
+
+    /// Override values with any set in the environment.
+    fn apply_env(&mut self, vars: &[(String, String)]) -> Result<()> {
+        if let Some(bind) = find_var(vars, BIND_ENV) {
+            self.server.bind = bind.to_owned();
+        }
+
+        if let Some(timeout) = find_var(vars, SHUTDOWN_TIMEOUT_ENV) {
+            self.server.shutdown_timeout_secs = parse_u64(SHUTDOWN_TIMEOUT_ENV, timeout)?;
+        }
+
+        if let Some(url) = find_var(vars, REDIS_URL_ENV) {
+            self.redis.url = url.to_owned();
+        }
+
+        if let Some(workers) = find_var(vars, WORKERS_ENV) {
+            self.limits.workers = parse_usize(WORKERS_ENV, workers)?;
+        }
+
+        if let Some(max) = find_var(vars, MAX_IN_FLIGHT_ENV) {
+            self.limits.max_in_flight = parse_usize(MAX_IN_FLIGHT_ENV, max)?;
+        }
+
+        if let Some(max) = find_var(vars, MAX_QUEUED_ENV) {
+            self.limits.max_queued = parse_usize(MAX_QUEUED_ENV, max)?;
+        }
+
+        if let Some(bind) = find_var(vars, TLS_BIND_ENV) {
+            self.tls_mut().bind = bind.to_owned();
+        }
+
+        if let Some(cert) = find_var(vars, TLS_CERT_ENV) {
+            self.tls_mut().cert = cert.to_owned();
+        }
+
+        if let Some(key) = find_var(vars, TLS_KEY_ENV) {
+            self.tls_mut().key = key.to_owned();
+        }
+
+        if let Some(client_ca) = find_var(vars, TLS_CLIENT_CA_ENV) {
+            self.tls_mut().client_ca = Some(client_ca.to_owned());
+        }
+
+        if let Some(require) = find_var(vars, TLS_REQUIRE_CLIENT_CERT_ENV) {
+            self.tls_mut().require_client_cert = parse_bool(TLS_REQUIRE_CLIENT_CERT_ENV, require)?;
+        }
+
+        Ok(())
+    }
+
+    /// Override values with any given on the command line.
+    fn apply_matches(&mut self, matches: &ArgMatches) -> Result<()> {
+        if let Some(bind) = matches.value_of("bind") {
+            self.server.bind = bind.to_owned();
+        }
+
+        if let Some(timeout) = matches.value_of("shutdown-timeout") {
+            self.server.shutdown_timeout_secs = parse_u64("--shutdown-timeout", timeout)?;
+        }
+
+        if let Some(url) = matches.value_of("redis-url") {
+            self.redis.url = url.to_owned();
+        }
+
+        if let Some(workers) = matches.value_of("workers") {
+            self.limits.workers = parse_usize("--workers", workers)?;
+        }
+
+        if let Some(max) = matches.value_of("max-in-flight") {
+            self.limits.max_in_flight = parse_usize("--max-in-flight", max)?;
+        }
+
+        if let Some(max) = matches.value_of("max-queued") {
+            self.limits.max_queued = parse_usize("--max-queued", max)?;
+        }
+
+        if let Some(bind) = matches.value_of("tls-bind") {
+            self.tls_mut().bind = bind.to_owned();
+        }
+
+        if let Some(cert) = matches.value_of("tls-cert") {
+            self.tls_mut().cert = cert.to_owned();
+        }
+
+        if let Some(key) = matches.value_of("tls-key") {
+            self.tls_mut().key = key.to_owned();
+        }
+
+        if let Some(client_ca) = matches.value_of("tls-client-ca") {
+            self.tls_mut().client_ca = Some(client_ca.to_owned());
+        }
+
+        if matches.is_present("tls-require-client-cert") {
+            self.tls_mut().require_client_cert = true;
+        }
+
+        Ok(())
+    }
+
+    /// Check the configuration makes sense.
+    pub fn validate(&self) -> Result<()> {
+        self.server.addr()?;
+
+        if !(self.redis.url.starts_with("redis://") || self.redis.url.starts_with("unix://")) {
+            bail!(invalid("redis.url", format!("'{}' should start with 'redis://' or 'unix://'", self.redis.url)));
+        }
+
+        if self.limits.workers == 0 {
+            bail!(invalid("limits.workers", "there must be at least 1 worker"));
+        }
+
+        if self.limits.max_in_flight == 0 {
+            bail!(invalid("limits.max_in_flight", "at least 1 request must be allowed in flight"));
+        }
+
+        if let Some(ref tls) = self.tls {
+            tls.validate()?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the TLS config, adding a default one if there isn't one already.
+    fn tls_mut(&mut self) -> &mut TlsConfig {
+        if self.tls.is_none() {
+            self.tls = Some(TlsConfig::default());
+        }
+
+        self.tls.as_mut().unwrap()
+    }
+}
+
+/// The command line interface shared by both binaries.
+fn app<'a, 'b>(name: &'a str) -> App<'a, 'b> {
+    App::new(name)
+        .arg(Arg::with_name("config")
+            .long("config")
+            .short("c")
+            .value_name("FILE")
+            .takes_value(true)
+            .help("Sets a toml config file to read"))
+        .arg(Arg::with_name("bind")
+            .long("bind")
+            .value_name("ADDR")
+            .takes_value(true)
+            .help("Sets the address to listen on"))
+        .arg(Arg::with_name("shutdown-timeout")
+            .long("shutdown-timeout")
+            .value_name("SECS")
+            .takes_value(true)
+            .help("Sets the seconds to wait for in-flight requests when shutting down"))
+        .arg(Arg::with_name("redis-url")
+            .long("redis-url")
+            .value_name("URL")
+            .takes_value(true)
+            .help("Sets the Redis url to connect to"))
+        .arg(Arg::with_name("workers")
+            .long("workers")
+            .value_name("N")
+            .takes_value(true)
+            .help("Sets the number of worker threads"))
+        .arg(Arg::with_name("max-in-flight")
+            .long("max-in-flight")
+            .value_name("N")
+            .takes_value(true)
+            .help("Sets the number of requests that can be handled at once"))
+        .arg(Arg::with_name("max-queued")
+            .long("max-queued")
+            .value_name("N")
+            .takes_value(true)
+            .help("Sets the number of requests that can wait to be handled"))
+        .arg(Arg::with_name("tls-bind")
+            .long("tls-bind")
+            .value_name("ADDR")
+            .takes_value(true)
+            .help("Sets the address to listen for HTTPS connections on"))
+        .arg(Arg::with_name("tls-cert")
+            .long("tls-cert")
+            .value_name("FILE")
+            .takes_value(true)
+            .help("Sets a PEM file with the server's certificate chain"))
+        .arg(Arg::with_name("tls-key")
+            .long("tls-key")
+            .value_name("FILE")
+            .takes_value(true)
+            .help("Sets a PEM file with the server's private key"))
+        .arg(Arg::with_name("tls-client-ca")
+            .long("tls-client-ca")
+            .value_name("FILE")
+            .takes_value(true)
+            .help("Sets a PEM file with CA certificates to verify client certificates against"))
+        .arg(Arg::with_name("tls-require-client-cert")
+            .long("tls-require-client-cert")
+            .help("Rejects HTTPS connections without a verified client certificate"))
+}
+
+fn find_var<'a>(vars: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    vars.iter()
+        .find(|&&(ref k, _)| k == key)
+        .map(|&(_, ref v)| v.as_ref())
+}
+
+fn parse_usize(key: &str, value: &str) -> Result<usize> {
+    value.parse()
+        .chain_err(|| invalid(key, format!("'{}' isn't a whole number", value)))
+}
+
+fn parse_u64(key: &str, value: &str) -> Result<u64> {
+    value.parse()
+        .chain_err(|| invalid(key, format!("'{}' isn't a whole number", value)))
+}
+
+fn parse_bool(key: &str, value: &str) -> Result<bool> {
+    value.parse()
+        .chain_err(|| invalid(key, format!("'{}' isn't 'true' or 'false'", value)))
+}
+
+fn invalid<K, M>(key: K, msg: M) -> ErrorKind
+    where K: Into<String>,
+          M: Into<String>
+{
+    ErrorKind::Invalid(key.into(), msg.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(args: &[&str]) -> ArgMatches<'static> {
+        let mut all = vec!["test"];
+        all.extend_from_slice(args);
+
+        app("test").get_matches_from(all)
+    }
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter().map(|&(k, v)| (k.to_owned(), v.to_owned())).collect()
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        let config = Config::default();
+
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn parse_partial_toml() {
+        let config = Config::from_toml("test.toml",
+                                       r#"
+            [server]
+            bind = "0.0.0.0:8080"
+            "#)
+            .unwrap();
+
+        assert_eq!("0.0.0.0:8080", config.server.bind);
+        assert_eq!(RedisConfig::default(), config.redis);
+    }
+
+    #[test]
+    fn parse_invalid_toml() {
+        let result = Config::from_toml("test.toml", "[server");
+
+        match *result.unwrap_err().kind() {
+            ErrorKind::Parse(ref source, _) => assert_eq!("test.toml", source),
+            ref kind => panic!("unexpected error: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn env_overrides_defaults() {
+        let config = Config::from_matches(&matches(&[]),
+                                          vars(&[(BIND_ENV, "0.0.0.0:8080"), (WORKERS_ENV, "8")]))
+            .unwrap();
+
+        assert_eq!("0.0.0.0:8080", config.server.bind);
+        assert_eq!(8, config.limits.workers);
+    }
+
+    #[test]
+    fn flags_override_env() {
+        let config = Config::from_matches(&matches(&["--workers", "2"]),
+                                          vars(&[(WORKERS_ENV, "8")]))
+            .unwrap();
+
+        assert_eq!(2, config.limits.workers);
+    }
+
+    #[test]
+    fn invalid_env_value() {
+        let result = Config::from_matches(&matches(&[]), vars(&[(WORKERS_ENV, "lots")]));
+
+        match *result.unwrap_err().kind() {
+            ErrorKind::Invalid(ref key, _) => assert_eq!(WORKERS_ENV, key),
+            ref kind => panic!("unexpected error: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn zero_workers_is_invalid() {
+        let mut config = Config::default();
+        config.limits.workers = 0;
+
+        match *config.validate().unwrap_err().kind() {
+            ErrorKind::Invalid(ref key, _) => assert_eq!("limits.workers", key),
+            ref kind => panic!("unexpected error: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn parse_limits_without_concurrency() {
+        let config = Config::from_toml("test.toml",
+                                       r#"
+            [limits]
+            workers = 2
+            "#)
+            .unwrap();
+
+        assert_eq!(2, config.limits.workers);
+        assert_eq!(LimitsConfig::default().max_in_flight, config.limits.max_in_flight);
+        assert_eq!(LimitsConfig::default().max_queued, config.limits.max_queued);
+    }
+
+    #[test]
+    fn parse_limits_without_workers() {
+        let config = Config::from_toml("test.toml",
+                                       r#"
+            [limits]
+            max_in_flight = 64
+            "#)
+            .unwrap();
+
+        assert_eq!(64, config.limits.max_in_flight);
+        assert_eq!(LimitsConfig::default().workers, config.limits.workers);
+    }
+
+    #[test]
+    fn zero_max_in_flight_is_invalid() {
+        let mut config = Config::default();
+        config.limits.max_in_flight = 0;
+
+        match *config.validate().unwrap_err().kind() {
+            ErrorKind::Invalid(ref key, _) => assert_eq!("limits.max_in_flight", key),
+            ref kind => panic!("unexpected error: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn tls_is_off_by_default() {
+        let config = Config::from_matches(&matches(&[]), vars(&[])).unwrap();
+
+        assert_eq!(None, config.tls);
+    }
+
+    #[test]
+    fn tls_without_key_is_invalid() {
+        let result = Config::from_matches(&matches(&["--tls-cert", "cert.pem"]), vars(&[]));
+
+        match *result.unwrap_err().kind() {
+            ErrorKind::Invalid(ref key, _) => assert_eq!("tls.key", key),
+            ref kind => panic!("unexpected error: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn flag_requires_client_cert() {
+        let mut config = Config::default();
+        config.apply_matches(&matches(&["--tls-require-client-cert"])).unwrap();
+
+        assert!(config.tls.unwrap().require_client_cert);
+    }
+
+    #[test]
+    fn env_sets_require_client_cert() {
+        let mut config = Config::default();
+        config.apply_env(&vars(&[(TLS_REQUIRE_CLIENT_CERT_ENV, "true")])).unwrap();
+
+        assert!(config.tls.unwrap().require_client_cert);
+    }
+
+    #[test]
+    fn invalid_require_client_cert_env_value() {
+        let mut config = Config::default();
+        let result = config.apply_env(&vars(&[(TLS_REQUIRE_CLIENT_CERT_ENV, "yes")]));
+
+        match *result.unwrap_err().kind() {
+            ErrorKind::Invalid(ref key, _) => assert_eq!(TLS_REQUIRE_CLIENT_CERT_ENV, key),
+            ref kind => panic!("unexpected error: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn invalid_redis_url() {
+        let mut config = Config::default();
+        config.redis.url = "127.0.0.1".to_owned();
+
+        match *config.validate().unwrap_err().kind() {
+            ErrorKind::Invalid(ref key, _) => assert_eq!("redis.url", key),
+            ref kind => panic!("unexpected error: {:?}", kind),
+        }
+    }
+}
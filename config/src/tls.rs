@@ -0,0 +1,157 @@
+//! # TLS
+//!
+//! Both apps can listen for HTTPS connections alongside plain HTTP ones.
+//! TLS is terminated in-process with [`rustls`](), using a certificate chain
+//! and private key read from PEM files.
+//!
+//! Clients can optionally be asked for a certificate too, which is useful
+//! for service-to-service calls.
+//! Client certificates are verified against the CA certificates in
+//! `client_ca`, and if `require_client_cert` is set then connections without
+//! a valid certificate are refused.
+//!
+//! ## Examples
+//!
+//! ```toml
+//! [tls]
+//! bind = "127.0.0.1:1338"
+//! cert = "/etc/webapp/cert.pem"
+//! key = "/etc/webapp/key.pem"
+//! client_ca = "/etc/webapp/ca.pem"
+//! require_client_cert = true
+//! ```
+
+use std::fs::File;
+use std::io::BufReader;
+use std::net::{SocketAddr, ToSocketAddrs};
+use rustls::{Certificate, PrivateKey, ServerConfig};
+use rustls::internal::pemfile;
+
+use errors::*;
+use super::invalid;
+
+/// Configuration for the HTTPS listener.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TlsConfig {
+    /// The address to listen for HTTPS connections on.
+    #[serde(default = "default_bind")]
+    pub bind: String,
+    /// The path to a PEM file with the server's certificate chain.
+    #[serde(default)]
+    pub cert: String,
+    /// The path to a PEM file with the server's private key.
+    #[serde(default)]
+    pub key: String,
+    /// The path to a PEM file with the CA certificates to verify client
+    /// certificates against.
+    #[serde(default)]
+    pub client_ca: Option<String>,
+    /// Whether clients must present a valid certificate.
+    #[serde(default)]
+    pub require_client_cert: bool,
+}
+
+fn default_bind() -> String {
+    "127.0.0.1:1338".to_owned()
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        TlsConfig {
+            bind: default_bind(),
+            cert: String::new(),
+            key: String::new(),
+            client_ca: None,
+            require_client_cert: false,
+        }
+    }
+}
+
+impl TlsConfig {
+    /// Resolve the address to listen on.
+    pub fn addr(&self) -> Result<SocketAddr> {
+        let mut addrs = self.bind
+            .to_socket_addrs()
+            .chain_err(|| invalid("tls.bind", format!("'{}' isn't a valid address", self.bind)))?;
+
+        match addrs.next() {
+            Some(addr) => Ok(addr),
+            None => bail!(invalid("tls.bind", format!("'{}' doesn't resolve to any addresses", self.bind))),
+        }
+    }
+
+    /// Check the configuration makes sense.
+    ///
+    /// This reads the certificates and key, so a missing or malformed
+    /// file is caught at startup.
+    pub fn validate(&self) -> Result<()> {
+        self.addr()?;
+        self.server_config()?;
+
+        if self.require_client_cert && self.client_ca.is_none() {
+            bail!(invalid("tls.require_client_cert",
+                          "client certificates can only be required if 'tls.client_ca' is set"));
+        }
+
+        Ok(())
+    }
+
+    /// Build a `rustls` server config from the certificates and key.
+    pub fn server_config(&self) -> Result<ServerConfig> {
+        if self.cert.is_empty() {
+            bail!(invalid("tls.cert", "a certificate is needed to listen for HTTPS connections"));
+        }
+
+        if self.key.is_empty() {
+            bail!(invalid("tls.key", "a private key is needed to listen for HTTPS connections"));
+        }
+
+        let certs = read_certs("tls.cert", &self.cert)?;
+        let key = read_key("tls.key", &self.key)?;
+
+        let mut config = ServerConfig::new();
+        config.set_single_cert(certs, key);
+
+        if let Some(ref client_ca) = self.client_ca {
+            let roots = read_certs("tls.client_ca", client_ca)?;
+
+            config.set_client_auth_roots(roots, self.require_client_cert);
+        }
+
+        Ok(config)
+    }
+}
+
+fn open(key: &str, path: &str) -> Result<BufReader<File>> {
+    let file = File::open(path).chain_err(|| invalid(key, format!("'{}' couldn't be opened", path)))?;
+
+    Ok(BufReader::new(file))
+}
+
+fn read_certs(key: &str, path: &str) -> Result<Vec<Certificate>> {
+    let certs = pemfile::certs(&mut open(key, path)?)
+        .map_err(|_| invalid(key, format!("'{}' isn't a valid PEM file", path)))?;
+
+    if certs.is_empty() {
+        bail!(invalid(key, format!("'{}' doesn't contain any certificates", path)));
+    }
+
+    Ok(certs)
+}
+
+fn read_key(key: &str, path: &str) -> Result<PrivateKey> {
+    // Keys may be in either the PKCS#1 or PKCS#8 format
+    let mut keys = pemfile::rsa_private_keys(&mut open(key, path)?)
+        .map_err(|_| invalid(key, format!("'{}' isn't a valid PEM file", path)))?;
+
+    if keys.is_empty() {
+        keys = pemfile::pkcs8_private_keys(&mut open(key, path)?)
+            .map_err(|_| invalid(key, format!("'{}' isn't a valid PEM file", path)))?;
+    }
+
+    match keys.len() {
+        0 => bail!(invalid(key, format!("'{}' doesn't contain a private key", path))),
+        1 => Ok(keys.remove(0)),
+        _ => bail!(invalid(key, format!("'{}' contains more than one private key", path))),
+    }
+}